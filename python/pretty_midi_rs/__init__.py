@@ -0,0 +1,5 @@
+from .pretty_midi_rs import *
+
+__doc__ = pretty_midi_rs.__doc__
+if hasattr(pretty_midi_rs, "__all__"):
+    __all__ = pretty_midi_rs.__all__
@@ -0,0 +1,533 @@
+"""Mirror of the object model of pretty-midi for reading MIDI files.
+
+The classes in this module mirror the constructors, attributes and methods of
+their pretty-midi counterparts, so that code reading MIDI files can often just
+swap its import::
+
+    from pretty_midi_rs import compat as pretty_midi
+
+    midi = pretty_midi.PrettyMIDI("song.mid")
+
+Parsing is done by the Rust extension, after which the data is copied into
+plain, mutable Python objects. Methods returning arrays require numpy.
+
+This is not a complete replacement. Writing files (``PrettyMIDI.write``),
+synthesis (``synthesize`` and ``fluidsynth``) and ``estimate_beat_start``
+raise ``NotImplementedError``.
+"""
+
+from . import pretty_midi_rs as _rs
+
+__all__ = [
+    "PrettyMIDI",
+    "Instrument",
+    "Note",
+    "PitchBend",
+    "ControlChange",
+    "KeySignature",
+    "TimeSignature",
+    "Lyric",
+    "Text",
+]
+
+_KEY_NAMES = ["C", "Db", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B"]
+
+
+def _decode(text, charset):
+    return text.encode("latin1").decode(charset)
+
+
+def _unsupported(name):
+    def method(self, *args, **kwargs):
+        raise NotImplementedError(
+            "{}.{} is not supported by pretty_midi_rs".format(type(self).__name__, name)
+        )
+
+    method.__name__ = name
+    return method
+
+
+class Note(object):
+    def __init__(self, velocity, pitch, start, end):
+        self.velocity = velocity
+        self.pitch = pitch
+        self.start = start
+        self.end = end
+
+    def get_duration(self):
+        return self.end - self.start
+
+    @property
+    def duration(self):
+        return self.get_duration()
+
+    def __repr__(self):
+        return "Note(start={:f}, end={:f}, pitch={}, velocity={})".format(
+            self.start, self.end, self.pitch, self.velocity
+        )
+
+
+class PitchBend(object):
+    def __init__(self, pitch, time):
+        self.pitch = pitch
+        self.time = time
+
+    def __repr__(self):
+        return "PitchBend(pitch={:d}, time={:f})".format(self.pitch, self.time)
+
+
+class ControlChange(object):
+    def __init__(self, number, value, time):
+        self.number = number
+        self.value = value
+        self.time = time
+
+    def __repr__(self):
+        return "ControlChange(number={:d}, value={:d}, time={:f})".format(
+            self.number, self.value, self.time
+        )
+
+
+class KeySignature(object):
+    def __init__(self, key_number, time):
+        if not (isinstance(key_number, int) and 0 <= key_number < 24):
+            raise ValueError("{} is not a valid `key_number` type or value".format(key_number))
+        if not (isinstance(time, (int, float)) and time >= 0):
+            raise ValueError("{} is not a valid `time` type or value".format(time))
+
+        self.key_number = key_number
+        self.time = time
+
+    def __repr__(self):
+        return "KeySignature(key_number={}, time={})".format(self.key_number, self.time)
+
+    def __str__(self):
+        mode = "Major" if self.key_number < 12 else "minor"
+        name = "{} {}".format(_KEY_NAMES[self.key_number % 12], mode)
+        return "{} at {:.2f} seconds".format(name, self.time)
+
+
+class TimeSignature(object):
+    def __init__(self, numerator, denominator, time):
+        if not (isinstance(numerator, int) and numerator > 0):
+            raise ValueError("{} is not a valid `numerator` type or value".format(numerator))
+        if not (isinstance(denominator, int) and denominator > 0):
+            raise ValueError("{} is not a valid `denominator` type or value".format(denominator))
+        if not (isinstance(time, (int, float)) and time >= 0):
+            raise ValueError("{} is not a valid `time` type or value".format(time))
+
+        self.numerator = numerator
+        self.denominator = denominator
+        self.time = time
+
+    def __repr__(self):
+        return "TimeSignature(numerator={}, denominator={}, time={})".format(
+            self.numerator, self.denominator, self.time
+        )
+
+    def __str__(self):
+        return "{}/{} at {:.2f} seconds".format(self.numerator, self.denominator, self.time)
+
+
+class Lyric(object):
+    def __init__(self, text, time):
+        self.text = text
+        self.time = time
+
+    def __repr__(self):
+        return 'Lyric(text="{}", time={})'.format(self.text.replace('"', r"\""), self.time)
+
+
+class Text(object):
+    def __init__(self, text, time):
+        self.text = text
+        self.time = time
+
+    def __repr__(self):
+        return 'Text(text="{}", time={})'.format(self.text.replace('"', r"\""), self.time)
+
+
+class Instrument(object):
+    def __init__(self, program, is_drum=False, name=""):
+        self.program = program
+        self.is_drum = is_drum
+        self.name = name
+        self.notes = []
+        self.pitch_bends = []
+        self.control_changes = []
+
+    @classmethod
+    def _from_rs(cls, instrument, charset="latin1"):
+        result = cls(instrument.program, instrument.is_drum, _decode(instrument.name, charset))
+        result.notes = [Note(n.velocity, n.pitch, n.start, n.end) for n in instrument.notes]
+        result.pitch_bends = [PitchBend(b.pitch, b.time) for b in instrument.pitch_bends]
+        result.control_changes = [
+            ControlChange(cc.number, cc.value, cc.time) for cc in instrument.control_changes
+        ]
+        return result
+
+    def get_onsets(self):
+        import numpy as np
+
+        return np.sort([note.start for note in self.notes])
+
+    def get_end_time(self):
+        events = (
+            [note.end for note in self.notes]
+            + [bend.time for bend in self.pitch_bends]
+            + [cc.time for cc in self.control_changes]
+        )
+        return max(events) if events else 0.0
+
+    def get_piano_roll(self, fs=100, times=None, pedal_threshold=64):
+        import numpy as np
+
+        if self.notes == []:
+            return np.zeros((128, 0))
+
+        end_time = self.get_end_time()
+        if times is not None and times[-1] > end_time:
+            end_time = times[-1]
+
+        piano_roll = np.zeros((128, int(fs * end_time)))
+        if self.is_drum:
+            return piano_roll if times is None else np.zeros((128, len(times)))
+
+        for note in self.notes:
+            piano_roll[note.pitch, int(note.start * fs) : int(note.end * fs)] += note.velocity
+
+        if pedal_threshold is not None:
+            pedal_on = None
+            for cc in self.control_changes:
+                if cc.number != 64:
+                    continue
+                now = int(cc.time * fs)
+                if pedal_on is None and cc.value >= pedal_threshold:
+                    pedal_on = now
+                elif pedal_on is not None and cc.value < pedal_threshold:
+                    # A held pedal retains the loudest velocity seen so far
+                    held = piano_roll[:, pedal_on:now]
+                    piano_roll[:, pedal_on:now] = np.maximum.accumulate(held, axis=1)
+                    pedal_on = None
+
+        bends = sorted(self.pitch_bends, key=lambda bend: bend.time)
+        for start_bend, end_bend in zip(bends, bends[1:] + [PitchBend(0, end_time)]):
+            if abs(start_bend.pitch) < 1:
+                continue
+            # Shift by whole semitones, then interpolate between neighbouring pitches by the rest
+            semitones = 2 * start_bend.pitch / 8192
+            bend_int = int(np.sign(semitones) * np.floor(np.abs(semitones)))
+            bend_decimal = np.abs(semitones - bend_int)
+            span = slice(int(start_bend.time * fs), int(end_bend.time * fs))
+            bent_roll = np.zeros(piano_roll[:, span].shape)
+            if start_bend.pitch >= 0:
+                if bend_int != 0:
+                    bent_roll[bend_int:] = piano_roll[:-bend_int, span]
+                else:
+                    bent_roll = piano_roll[:, span].copy()
+                bent_roll[1:] = (1 - bend_decimal) * bent_roll[1:] + bend_decimal * bent_roll[:-1]
+            else:
+                if bend_int != 0:
+                    bent_roll[:bend_int] = piano_roll[-bend_int:, span]
+                else:
+                    bent_roll = piano_roll[:, span].copy()
+                bent_roll[:-1] = (1 - bend_decimal) * bent_roll[:-1] + bend_decimal * bent_roll[1:]
+            piano_roll[:, span] = bent_roll
+
+        if times is None:
+            return piano_roll
+
+        integrated = np.zeros((128, len(times)))
+        columns = np.array(np.round(np.asarray(times) * fs), dtype=int)
+        for n, (start, end) in enumerate(zip(columns[:-1], columns[1:])):
+            if start < piano_roll.shape[1]:
+                end = max(end, start + 1)
+                integrated[:, n] = np.mean(piano_roll[:, start:end], axis=1)
+        return integrated
+
+    def get_chroma(self, fs=100, times=None, pedal_threshold=64):
+        return _roll_to_chroma(self.get_piano_roll(fs, times, pedal_threshold))
+
+    def remove_invalid_notes(self):
+        self.notes = [note for note in self.notes if note.end > note.start]
+
+    def _note_tuples(self):
+        return [(note.pitch, note.velocity, float(note.start), float(note.end)) for note in self.notes]
+
+    def get_pitch_class_histogram(self, use_duration=False, use_velocity=False, normalize=False):
+        import numpy as np
+
+        return np.array(
+            _rs.pitch_class_histogram(
+                self._note_tuples(), self.is_drum, use_duration, use_velocity, normalize
+            )
+        )
+
+    def get_pitch_class_transition_matrix(self, normalize=False, time_thresh=0.05):
+        import numpy as np
+
+        return np.array(
+            _rs.pitch_class_transition_matrix(self._note_tuples(), self.is_drum, normalize, time_thresh)
+        )
+
+    synthesize = _unsupported("synthesize")
+    fluidsynth = _unsupported("fluidsynth")
+
+    def __repr__(self):
+        return 'Instrument(program={}, is_drum={}, name="{}")'.format(
+            self.program, self.is_drum, self.name.replace('"', r"\"")
+        )
+
+
+class PrettyMIDI(object):
+    def __init__(self, midi_file=None, resolution=220, initial_tempo=120.0, charset="latin1"):
+        self._charset = charset
+        # Text is read as latin1, which keeps the original bytes, and decoded with `charset` here
+        if midi_file is None:
+            self._midi = _rs.MidiObject(None, resolution, float(initial_tempo))
+        elif isinstance(midi_file, str):
+            self._midi = _rs.MidiObject(midi_file, charset="latin1")
+        else:
+            self._midi = _rs.MidiObject.from_bytes(midi_file.read(), charset="latin1")
+        self._read()
+
+    def _read(self):
+        """Copies the state of the Rust object into the Python objects."""
+        midi, charset = self._midi, self._charset
+        self.resolution = midi.resolution
+        self.instruments = [Instrument._from_rs(i, charset) for i in midi.instruments]
+        self.key_signature_changes = [
+            KeySignature(ks.key_number, ks.time) for ks in midi.key_signature_changes
+        ]
+        self.time_signature_changes = [
+            TimeSignature(ts.numerator, ts.denominator, ts.time)
+            for ts in midi.time_signature_changes
+        ]
+        self.lyrics = [Lyric(_decode(lyric.text, charset), lyric.time) for lyric in midi.lyrics]
+        self.text_events = [Text(_decode(text.text, charset), text.time) for text in midi.text_events]
+        self._tick_scales = midi.tick_scales
+
+    def get_tempo_changes(self):
+        import numpy as np
+
+        times, tempi = self._midi.get_tempo_changes()
+        return np.array(times), np.array(tempi)
+
+    def tick_to_time(self, tick):
+        return self._midi.tick_to_time(int(tick))
+
+    def time_to_tick(self, time):
+        return self._midi.time_to_tick(float(time))
+
+    def get_end_time(self):
+        meta_events = (
+            self.key_signature_changes
+            + self.time_signature_changes
+            + self.lyrics
+            + self.text_events
+        )
+        times = (
+            [i.get_end_time() for i in self.instruments]
+            + [event.time for event in meta_events]
+            + list(self._midi.get_tempo_changes()[0])
+        )
+        return max(times) if times else 0.0
+
+    def get_onsets(self):
+        import numpy as np
+
+        onsets = [note.start for i in self.instruments for note in i.notes]
+        return np.sort(onsets)
+
+    def get_piano_roll(self, fs=100, times=None, pedal_threshold=64):
+        import numpy as np
+
+        if len(self.instruments) == 0:
+            return np.zeros((128, 0))
+
+        rolls = [i.get_piano_roll(fs, times, pedal_threshold) for i in self.instruments]
+        piano_roll = np.zeros((128, max(roll.shape[1] for roll in rolls)))
+        for roll in rolls:
+            piano_roll[:, : roll.shape[1]] += roll
+        return piano_roll
+
+    def get_chroma(self, fs=100, times=None, pedal_threshold=64):
+        return _roll_to_chroma(self.get_piano_roll(fs, times, pedal_threshold))
+
+    def remove_invalid_notes(self):
+        for instrument in self.instruments:
+            instrument.remove_invalid_notes()
+
+    def adjust_times(self, original_times, new_times):
+        """Warps the file in Rust and reads it back, so changes made to the
+        Python objects since loading are replaced."""
+        self._midi.adjust_times([float(t) for t in original_times], [float(t) for t in new_times])
+        self._read()
+
+    def get_pitch_class_histogram(self, use_duration=False, use_velocity=False, normalize=False):
+        import numpy as np
+
+        histogram = np.zeros(12)
+        for instrument in self.instruments:
+            histogram += instrument.get_pitch_class_histogram(use_duration, use_velocity)
+        if normalize and histogram.sum() > 0:
+            histogram /= histogram.sum()
+        return histogram
+
+    def get_pitch_class_transition_matrix(self, normalize=False, time_thresh=0.05):
+        import numpy as np
+
+        matrix = np.zeros((12, 12))
+        for instrument in self.instruments:
+            matrix += instrument.get_pitch_class_transition_matrix(time_thresh=time_thresh)
+        if normalize and matrix.sum() > 0:
+            matrix /= matrix.sum()
+        return matrix
+
+    def get_beats(self, start_time=0.0):
+        import numpy as np
+
+        tempo_change_times, tempi = self.get_tempo_changes()
+        beats = [start_time]
+        tempo_idx = 0
+        while tempo_idx < tempo_change_times.shape[0] - 1 and beats[-1] > tempo_change_times[tempo_idx + 1]:
+            tempo_idx += 1
+        self.time_signature_changes.sort(key=lambda ts: ts.time)
+        ts_idx = 0
+        while (
+            ts_idx < len(self.time_signature_changes) - 1
+            and beats[-1] >= self.time_signature_changes[ts_idx + 1].time
+        ):
+            ts_idx += 1
+
+        def get_current_bpm():
+            if self.time_signature_changes:
+                signature = self.time_signature_changes[ts_idx]
+                return _qpm_to_bpm(tempi[tempo_idx], signature.numerator, signature.denominator)
+            return tempi[tempo_idx]
+
+        def gt_or_close(a, b):
+            return a > b or np.isclose(a, b)
+
+        end_time = self.get_end_time()
+        while beats[-1] < end_time:
+            next_beat = beats[-1] + 60.0 / get_current_bpm()
+            # A beat passing a tempo change is split between the tempi
+            if tempo_idx < tempo_change_times.shape[0] - 1 and next_beat > tempo_change_times[tempo_idx + 1]:
+                next_beat = beats[-1]
+                beat_remaining = 1.0
+                while (
+                    tempo_idx < tempo_change_times.shape[0] - 1
+                    and next_beat + beat_remaining * 60.0 / tempi[tempo_idx] >= tempo_change_times[tempo_idx + 1]
+                ):
+                    overshot_ratio = (tempo_change_times[tempo_idx + 1] - next_beat) / (60.0 / tempi[tempo_idx])
+                    next_beat += overshot_ratio * 60.0 / tempi[tempo_idx]
+                    beat_remaining -= overshot_ratio
+                    tempo_idx += 1
+                next_beat += beat_remaining * 60.0 / tempi[tempo_idx]
+            # Beats snap to the first time signature change
+            if self.time_signature_changes and ts_idx == 0:
+                current_ts_time = self.time_signature_changes[ts_idx].time
+                if current_ts_time > beats[-1] and gt_or_close(next_beat, current_ts_time):
+                    next_beat = current_ts_time
+            # and to every later one
+            if ts_idx < len(self.time_signature_changes) - 1:
+                next_ts_time = self.time_signature_changes[ts_idx + 1].time
+                if gt_or_close(next_beat, next_ts_time):
+                    next_beat = next_ts_time
+                    ts_idx += 1
+            beats.append(next_beat)
+        # The last beat is past the end of the file
+        return np.array(beats[:-1])
+
+    def get_downbeats(self, start_time=0.0):
+        import numpy as np
+
+        beats = self.get_beats(start_time)
+        time_signatures = [
+            TimeSignature(ts.numerator, ts.denominator, ts.time) for ts in self.time_signature_changes
+        ]
+        if not time_signatures or time_signatures[0].time > start_time:
+            time_signatures.insert(0, TimeSignature(4, 4, start_time))
+
+        def index(array, value, default):
+            idx = np.flatnonzero(np.isclose(array, value))
+            return idx[0] if idx.size > 0 else default
+
+        def beats_per_bar(signature):
+            # Compound meters count their beats in dotted notes
+            if signature.numerator % 3 == 0 and signature.numerator != 3:
+                return signature.numerator // 3
+            return signature.numerator
+
+        downbeats = []
+        end_beat_idx = 0
+        for start_ts, end_ts in zip(time_signatures[:-1], time_signatures[1:]):
+            start_beat_idx = index(beats, start_ts.time, 0)
+            end_beat_idx = index(beats, end_ts.time, start_beat_idx)
+            downbeats.append(beats[start_beat_idx:end_beat_idx:beats_per_bar(start_ts)])
+        final_ts = time_signatures[-1]
+        start_beat_idx = index(beats, final_ts.time, end_beat_idx)
+        downbeats.append(beats[start_beat_idx :: beats_per_bar(final_ts)])
+        downbeats = np.concatenate(downbeats)
+        return downbeats[downbeats >= start_time]
+
+    def estimate_tempi(self):
+        import numpy as np
+
+        ioi = np.diff(self.get_onsets())
+        # Rhythmic information is carried by inter-onset intervals between 50 ms and 2 s
+        ioi = ioi[(ioi > 0.05) & (ioi < 2)]
+        for n in range(ioi.shape[0]):
+            while ioi[n] < 0.2:
+                ioi[n] *= 2
+
+        # Clusters intervals within 25 ms of each other
+        clusters = np.array([])
+        cluster_counts = np.array([])
+        for interval in ioi:
+            if (np.abs(clusters - interval) < 0.025).any():
+                k = np.argmin(clusters - interval)
+                clusters[k] = (cluster_counts[k] * clusters[k] + interval) / (cluster_counts[k] + 1)
+                cluster_counts[k] += 1
+            else:
+                clusters = np.append(clusters, interval)
+                cluster_counts = np.append(cluster_counts, 1.0)
+
+        cluster_sort = np.argsort(cluster_counts)[::-1]
+        clusters = clusters[cluster_sort]
+        cluster_counts = cluster_counts[cluster_sort]
+        cluster_counts /= cluster_counts.sum()
+        return 60.0 / clusters, cluster_counts
+
+    def estimate_tempo(self):
+        tempi = self.estimate_tempi()[0]
+        if tempi.size == 0:
+            raise ValueError("Can't provide a global tempo estimate when there are fewer than two notes.")
+        return tempi[0]
+
+    estimate_beat_start = _unsupported("estimate_beat_start")
+    synthesize = _unsupported("synthesize")
+    fluidsynth = _unsupported("fluidsynth")
+    write = _unsupported("write")
+
+
+def _qpm_to_bpm(quarter_note_tempo, numerator, denominator):
+    # Simple triple meters count the denominator, compound meters dotted notes
+    if denominator in [1, 2, 4, 8, 16, 32]:
+        if numerator == 3:
+            return quarter_note_tempo * denominator / 4.0
+        elif numerator % 3 == 0:
+            return quarter_note_tempo / 3.0 * denominator / 4.0
+        else:
+            return quarter_note_tempo * denominator / 4.0
+    return quarter_note_tempo
+
+
+def _roll_to_chroma(piano_roll):
+    import numpy as np
+
+    chroma = np.zeros((12, piano_roll.shape[1]))
+    for pitch_class in range(12):
+        chroma[pitch_class] = np.sum(piano_roll[pitch_class::12], axis=0)
+    return chroma
@@ -1,5 +1,5 @@
 use pyo3::{
-    exceptions::{PyBaseException, PyIOError},
+    exceptions::{PyBaseException, PyIOError, PyValueError},
     prelude::*,
 };
 
@@ -7,7 +7,8 @@ use pyo3::{
 pub enum ErrorKind {
     IO,
     Midly,
-    Generic,
+    Value,
+    Unsupported,
 }
 
 #[derive(Debug)]
@@ -16,6 +17,24 @@ pub struct Error {
     kind: ErrorKind,
 }
 
+impl Error {
+    /// Creates an error for an argument with an invalid value.
+    pub fn value(msg: impl Into<String>) -> Self {
+        Self {
+            inner: msg.into().into(),
+            kind: ErrorKind::Value,
+        }
+    }
+
+    /// Creates an error for a file using a feature that is not supported.
+    pub fn unsupported(msg: impl Into<String>) -> Self {
+        Self {
+            inner: msg.into().into(),
+            kind: ErrorKind::Unsupported,
+        }
+    }
+}
+
 impl From<midly::Error> for Error {
     fn from(err: midly::Error) -> Self {
         Self {
@@ -40,7 +59,7 @@ impl std::fmt::Display for Error {
             f,
             "[evalpy err: {:?}]: {}",
             self.kind,
-            self.inner.to_string()
+            self.inner
         )
     }
 }
@@ -52,7 +71,8 @@ impl From<Error> for PyErr {
         let msg = err.to_string();
         match err.kind {
             ErrorKind::IO => PyIOError::new_err(msg),
-            ErrorKind::Midly | ErrorKind::Generic => PyBaseException::new_err(msg),
+            ErrorKind::Value | ErrorKind::Unsupported => PyValueError::new_err(msg),
+            ErrorKind::Midly => PyBaseException::new_err(msg),
         }
     }
 }
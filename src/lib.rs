@@ -1,22 +1,20 @@
-#![feature(drain_filter)]
 #![feature(iter_collect_into)]
 #![feature(type_changing_struct_update)]
 
-/// A Rust implementation of the python library "pretty-midi".
+//! A Rust implementation of the python library "pretty-midi".
 
-mod midi;
+pub mod midi;
 mod err;
 
 use err::*;
 
 use std::ops::Deref;
-use std::{fs::File, rc::Rc};
+use std::fs::File;
 use std::{sync::Arc};
 use std::io::Read;
-use pyo3::{
-    exceptions::{PyBaseException, PyIOError},
-    prelude::*,
-};
+use pyo3::prelude::*;
+use pyo3::buffer::PyBuffer;
+use pyo3::types::PyDict;
 
 #[derive(Clone)]
 struct RcLens<T, U: 'static>(Arc<T>, &'static U);
@@ -46,9 +44,311 @@ impl<T, U> Deref for RcLens<T, U> {
 fn pretty_midi_rs(_: Python<'_>, m: &PyModule) -> PyResult<()> {
     m.add_class::<Instrument>()?;
     m.add_class::<MidiObject>()?;
+    m.add_class::<PitchBend>()?;
+    m.add_class::<ControlChange>()?;
+    m.add_class::<KeySignature>()?;
+    m.add_class::<TimeSignature>()?;
+    m.add_class::<Lyric>()?;
+    m.add_class::<Text>()?;
+    m.add_class::<DanglingNote>()?;
+    m.add_class::<BeatNote>()?;
+    m.add_class::<TickNote>()?;
+    m.add_class::<TickPitchBend>()?;
+    m.add_class::<TickControlChange>()?;
+    m.add_class::<TickInstrument>()?;
+    m.add_class::<TickMidiObject>()?;
+    m.add_class::<PedalInterval>()?;
+    m.add_class::<Stats>()?;
+    m.add_class::<InstrumentStats>()?;
+    m.add_class::<KeyEstimate>()?;
+    m.add_class::<Chord>()?;
+    m.add_class::<AlignedNote>()?;
+    m.add_class::<Alignment>()?;
+    m.add_function(wrap_pyfunction!(piano_roll_to_instrument, m)?)?;
+    m.add_function(wrap_pyfunction!(evaluate_transcription, m)?)?;
+    m.add_function(wrap_pyfunction!(evaluate_transcription_velocity, m)?)?;
+    m.add_function(wrap_pyfunction!(evaluate_frames, m)?)?;
+    m.add_function(wrap_pyfunction!(pitch_class_histogram, m)?)?;
+    m.add_function(wrap_pyfunction!(pitch_class_transition_matrix, m)?)?;
     Ok(())
 }
 
+/// Turns a piano roll, a 128 by T matrix of velocities in frames of `1 / fs` seconds, into an instrument
+/// with the given program. A note starts where a pitch becomes active and lasts until it falls silent,
+/// taking the velocity of its first frame, rounded and limited to 1 to 127. When an `onsets` matrix
+/// of the same shape is given, a note also starts on every active frame with a nonzero onset,
+/// re-articulating held pitches. The matrices are numpy arrays or other sequences of rows,
+/// float64 arrays being read without going through Python objects.
+/// The instrument uses the default tempo of 120 beats per minute.
+#[pyfunction]
+fn piano_roll_to_instrument(
+    py: Python<'_>,
+    roll: &PyAny,
+    fs: Option<f64>,
+    program: Option<u8>,
+    onsets: Option<&PyAny>,
+) -> PyResult<Instrument> {
+    let roll = extract_matrix(py, roll)?;
+    let onsets = onsets.map(|onsets| extract_matrix(py, onsets)).transpose()?;
+    let fs = fs.unwrap_or(100.0);
+    if fs <= 0.0 {
+        return Err(Error::value("the sampling frequency has to be positive").into());
+    }
+    if roll.len() != 128 {
+        return Err(Error::value(format!("a piano roll needs 128 rows, got {}", roll.len())).into());
+    }
+    let frames = roll[0].len();
+    if roll.iter().any(|row| row.len() != frames) {
+        return Err(Error::value("the rows of the piano roll have to be of the same length").into());
+    }
+    if let Some(onsets) = &onsets {
+        if onsets.len() != roll.len() || onsets.iter().any(|row| row.len() != frames) {
+            return Err(Error::value("the onsets have to be of the same shape as the piano roll").into());
+        }
+    }
+
+    let instrument = midi::piano_roll_to_instrument(&roll, fs, program.unwrap_or(0), onsets.as_deref());
+    let converter = midi::TickConverter::new(&[(0, midi::DEFAULT_TEMPO)], midi::DEFAULT_RESOLUTION);
+    Ok(Instrument(Arc::new(instrument), Arc::new(converter), None))
+}
+
+/// Reads a matrix as its rows. Two dimensional float64 buffers, like most numpy arrays,
+/// are copied in one go, anything else is read element by element.
+fn extract_matrix(py: Python<'_>, matrix: &PyAny) -> PyResult<Vec<Vec<f64>>> {
+    if let Ok(buffer) = PyBuffer::<f64>::get(matrix) {
+        if let [rows, columns] = *buffer.shape() {
+            let values = buffer.to_vec(py)?;
+            if columns == 0 {
+                return Ok(vec![vec![]; rows]);
+            }
+            return Ok(values.chunks(columns).map(<[f64]>::to_vec).collect());
+        }
+    }
+    matrix.extract()
+}
+
+/// Returns the pitch class histogram of notes given as `(pitch, velocity, start, end)` tuples,
+/// see `Instrument.get_pitch_class_histogram`.
+#[pyfunction]
+fn pitch_class_histogram(
+    notes: Vec<(u8, u8, f64, f64)>,
+    is_drum: Option<bool>,
+    use_duration: Option<bool>,
+    use_velocity: Option<bool>,
+    normalize: Option<bool>,
+) -> Vec<f64> {
+    tuple_instrument(notes, is_drum.unwrap_or(false))
+        .pitch_class_histogram(
+            use_duration.unwrap_or(false),
+            use_velocity.unwrap_or(false),
+            normalize.unwrap_or(false),
+        )
+        .to_vec()
+}
+
+/// Returns the pitch class transition matrix of notes given as `(pitch, velocity, start, end)` tuples,
+/// see `Instrument.get_pitch_class_transition_matrix`.
+#[pyfunction]
+fn pitch_class_transition_matrix(
+    notes: Vec<(u8, u8, f64, f64)>,
+    is_drum: Option<bool>,
+    normalize: Option<bool>,
+    time_thresh: Option<f64>,
+) -> Vec<Vec<f64>> {
+    tuple_instrument(notes, is_drum.unwrap_or(false))
+        .pitch_class_transition_matrix(
+            normalize.unwrap_or(false),
+            time_thresh.unwrap_or(DEFAULT_TRANSITION_THRESHOLD),
+        )
+        .iter()
+        .map(|row| row.to_vec())
+        .collect()
+}
+
+/// Builds an instrument holding notes given as `(pitch, velocity, start, end)` tuples.
+fn tuple_instrument(notes: Vec<(u8, u8, f64, f64)>, is_drum: bool) -> midi::Instrument<midi::RealTime> {
+    midi::Instrument {
+        program: 0,
+        is_drum,
+        name: String::new(),
+        notes: notes
+            .into_iter()
+            .map(|(pitch, velocity, start_time, end_time)| midi::Note { pitch, velocity, start_time, end_time })
+            .collect(),
+        pitch_bends: vec![],
+        control_changes: vec![],
+    }
+}
+
+/// Scores the notes of an estimated transcription against those of a reference like
+/// `mir_eval.transcription.evaluate`, returning a dict with the same keys. Both are either an `Instrument`
+/// or a `MidiObject`, whose pitched instruments are evaluated together. Notes match when they have
+/// the same pitch, onsets at most `onset_tolerance` seconds apart and, for the scores taking offsets
+/// into account, offsets at most `offset_ratio` times the duration of the reference note apart,
+/// or `offset_min_tolerance` seconds if that is longer. With `strict` the distances have to be below
+/// the tolerances. The pitch tolerance is given in cents, ignoring pitch bends.
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+fn evaluate_transcription(
+    py: Python<'_>,
+    reference: &PyAny,
+    estimate: &PyAny,
+    onset_tolerance: Option<f64>,
+    pitch_tolerance: Option<f64>,
+    offset_ratio: Option<f64>,
+    offset_min_tolerance: Option<f64>,
+    strict: Option<bool>,
+    beta: Option<f64>,
+) -> PyResult<Py<PyDict>> {
+    let (reference, estimate) = (transcription_notes(py, reference)?, transcription_notes(py, estimate)?);
+    let tolerances = note_tolerances(onset_tolerance, pitch_tolerance, offset_ratio, offset_min_tolerance, strict)?;
+    let beta = beta.unwrap_or(1.0);
+    let without_offsets = midi::NoteTolerances { offset_ratio: None, ..tolerances };
+
+    let with = |matching: Vec<(usize, usize)>| midi::note_scores(&reference, &estimate, &matching, beta);
+    let notes = with(midi::match_notes(&reference, &estimate, &tolerances));
+    let onsets_only = with(midi::match_notes(&reference, &estimate, &without_offsets));
+    let onsets = with(midi::match_note_onsets(&reference, &estimate, &tolerances));
+    let offsets = with(midi::match_note_offsets(&reference, &estimate, &tolerances));
+
+    scores_dict(
+        py,
+        &[
+            ("Precision", notes.precision),
+            ("Recall", notes.recall),
+            ("F-measure", notes.f_measure),
+            ("Average_Overlap_Ratio", notes.average_overlap_ratio),
+            ("Precision_no_offset", onsets_only.precision),
+            ("Recall_no_offset", onsets_only.recall),
+            ("F-measure_no_offset", onsets_only.f_measure),
+            ("Average_Overlap_Ratio_no_offset", onsets_only.average_overlap_ratio),
+            ("Onset_Precision", onsets.precision),
+            ("Onset_Recall", onsets.recall),
+            ("Onset_F-measure", onsets.f_measure),
+            ("Offset_Precision", offsets.precision),
+            ("Offset_Recall", offsets.recall),
+            ("Offset_F-measure", offsets.f_measure),
+        ],
+    )
+}
+
+/// Scores the notes of an estimated transcription against those of a reference taking velocities
+/// into account like `mir_eval.transcription_velocity.evaluate`, returning a dict with the same keys.
+/// Notes match as in `evaluate_transcription` and when their velocities, the reference ones scaled
+/// to the range from 0 to 1 and the estimated ones fitted onto them, differ by less than `velocity_tolerance`.
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+fn evaluate_transcription_velocity(
+    py: Python<'_>,
+    reference: &PyAny,
+    estimate: &PyAny,
+    onset_tolerance: Option<f64>,
+    pitch_tolerance: Option<f64>,
+    offset_ratio: Option<f64>,
+    offset_min_tolerance: Option<f64>,
+    strict: Option<bool>,
+    velocity_tolerance: Option<f64>,
+    beta: Option<f64>,
+) -> PyResult<Py<PyDict>> {
+    let (reference, estimate) = (transcription_notes(py, reference)?, transcription_notes(py, estimate)?);
+    let tolerances = note_tolerances(onset_tolerance, pitch_tolerance, offset_ratio, offset_min_tolerance, strict)?;
+    let velocity_tolerance = velocity_tolerance.unwrap_or(0.1);
+    let beta = beta.unwrap_or(1.0);
+    let without_offsets = midi::NoteTolerances { offset_ratio: None, ..tolerances };
+
+    let scores = |tolerances: &midi::NoteTolerances| {
+        let matching = midi::match_notes_with_velocity(&reference, &estimate, tolerances, velocity_tolerance);
+        midi::note_scores(&reference, &estimate, &matching, beta)
+    };
+    let notes = scores(&tolerances);
+    let onsets_only = scores(&without_offsets);
+
+    scores_dict(
+        py,
+        &[
+            ("Precision", notes.precision),
+            ("Recall", notes.recall),
+            ("F-measure", notes.f_measure),
+            ("Average_Overlap_Ratio", notes.average_overlap_ratio),
+            ("Precision_no_offset", onsets_only.precision),
+            ("Recall_no_offset", onsets_only.recall),
+            ("F-measure_no_offset", onsets_only.f_measure),
+            ("Average_Overlap_Ratio_no_offset", onsets_only.average_overlap_ratio),
+        ],
+    )
+}
+
+/// Compares the frames in which pitches are active in an estimated transcription and a reference,
+/// returning a dict with the 'Precision', 'Recall', 'F-measure' and 'Accuracy' of the active frames.
+/// Both are either an `Instrument`, a `MidiObject`, whose pitched instruments are evaluated together,
+/// or a piano roll of 128 rows whose nonzero values mark the active frames. Notes are turned into
+/// frames of `1 / fs` seconds, 100 per second by default, like `Instrument.get_piano_roll`.
+#[pyfunction]
+fn evaluate_frames(py: Python<'_>, reference: &PyAny, estimate: &PyAny, fs: Option<f64>) -> PyResult<Py<PyDict>> {
+    let fs = fs.unwrap_or(100.0);
+    if fs <= 0.0 {
+        return Err(Error::value("the sampling frequency has to be positive").into());
+    }
+
+    let frames = |source: &PyAny| -> PyResult<Vec<Vec<bool>>> {
+        if let Ok(roll) = source.extract::<Vec<Vec<f64>>>() {
+            return Ok(roll.iter().map(|row| row.iter().map(|value| *value != 0.0).collect()).collect());
+        }
+        Ok(midi::active_frames(&transcription_notes(py, source)?, fs))
+    };
+    let scores = midi::frame_scores(&frames(reference)?, &frames(estimate)?, 1.0);
+
+    scores_dict(
+        py,
+        &[
+            ("Precision", scores.precision),
+            ("Recall", scores.recall),
+            ("F-measure", scores.f_measure),
+            ("Accuracy", scores.accuracy),
+        ],
+    )
+}
+
+/// Collects the notes of an instrument, or of the pitched instruments of a MIDI object in their order.
+fn transcription_notes(py: Python<'_>, source: &PyAny) -> PyResult<Vec<midi::Note<midi::RealTime>>> {
+    if let Ok(instrument) = source.extract::<PyRef<Instrument>>() {
+        return Ok(instrument.0.notes.clone());
+    }
+    Ok(source.extract::<PyRef<MidiObject>>()?.pitched_notes(py).0)
+}
+
+/// Reads the tolerances of note matching, defaulting to those of `mir_eval`.
+fn note_tolerances(
+    onset: Option<f64>,
+    pitch: Option<f64>,
+    offset_ratio: Option<f64>,
+    offset_min: Option<f64>,
+    strict: Option<bool>,
+) -> Result<midi::NoteTolerances, Error> {
+    let defaults = midi::NoteTolerances::default();
+    let tolerances = midi::NoteTolerances {
+        onset: onset.unwrap_or(defaults.onset),
+        pitch: pitch.unwrap_or(defaults.pitch),
+        offset_ratio: offset_ratio.or(defaults.offset_ratio),
+        offset_min: offset_min.unwrap_or(defaults.offset_min),
+        strict: strict.unwrap_or(defaults.strict),
+    };
+    let values = [tolerances.onset, tolerances.pitch, tolerances.offset_ratio.unwrap_or(0.0), tolerances.offset_min];
+    if values.iter().any(|value| value.is_nan() || *value < 0.0) {
+        return Err(Error::value("the tolerances can not be negative"));
+    }
+    Ok(tolerances)
+}
+
+/// Builds a dict of scores keeping their order, like the ordered dicts of `mir_eval`.
+fn scores_dict(py: Python<'_>, scores: &[(&str, f64)]) -> PyResult<Py<PyDict>> {
+    let dict = PyDict::new(py);
+    for (name, score) in scores {
+        dict.set_item(name, score)?;
+    }
+    Ok(dict.into())
+}
+
 #[pyclass]
 struct Note(RcLens<midi::Instrument<midi::RealTime>, midi::Note<midi::RealTime>>);
 
@@ -65,12 +365,12 @@ impl Note {
     }
     
     #[getter]
-    fn start(&self) -> f32 {
+    fn start(&self) -> f64 {
         self.0.start_time
     }
     
     #[getter]
-    fn end(&self) -> f32 {
+    fn end(&self) -> f64 {
         self.0.end_time
     }
 }
@@ -125,7 +425,519 @@ impl NoteIter {
 
 #[pyclass]
 #[derive(Clone)]
-struct Instrument(Arc<midi::Instrument<midi::RealTime>>);
+struct PitchBend {
+    #[pyo3(get)]
+    pitch: i16,
+    #[pyo3(get)]
+    time: f64,
+}
+
+impl From<&midi::PitchBend<midi::RealTime>> for PitchBend {
+    fn from(bend: &midi::PitchBend<midi::RealTime>) -> Self {
+        PitchBend {
+            pitch: bend.pitch(),
+            time: bend.time,
+        }
+    }
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct ControlChange {
+    #[pyo3(get)]
+    number: u8,
+    #[pyo3(get)]
+    value: u8,
+    #[pyo3(get)]
+    time: f64,
+}
+
+impl From<&midi::ControlChange<midi::RealTime>> for ControlChange {
+    fn from(cc: &midi::ControlChange<midi::RealTime>) -> Self {
+        ControlChange {
+            number: cc.number,
+            value: cc.value,
+            time: cc.time,
+        }
+    }
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct KeySignature {
+    #[pyo3(get)]
+    key_number: u8,
+    #[pyo3(get)]
+    time: f64,
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct TimeSignature {
+    #[pyo3(get)]
+    numerator: u8,
+    #[pyo3(get)]
+    denominator: u8,
+    #[pyo3(get)]
+    time: f64,
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct Lyric {
+    #[pyo3(get)]
+    text: String,
+    #[pyo3(get)]
+    time: f64,
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct Text {
+    #[pyo3(get)]
+    text: String,
+    #[pyo3(get)]
+    time: f64,
+}
+
+/// A note that was started but never ended before the end of its track.
+#[pyclass]
+#[derive(Clone)]
+struct DanglingNote {
+    #[pyo3(get)]
+    pitch: u8,
+    #[pyo3(get)]
+    velocity: u8,
+    #[pyo3(get)]
+    start: f64,
+    #[pyo3(get)]
+    track: usize,
+    #[pyo3(get)]
+    channel: u8,
+}
+
+const DEFAULT_PEDAL_THRESHOLD: u8 = 64;
+/// The default time within which two notes are considered a transition, like pretty-midi.
+const DEFAULT_TRANSITION_THRESHOLD: f64 = 0.05;
+
+/// The stretch of time during which a pedal is held down.
+#[pyclass]
+#[derive(Clone)]
+struct PedalInterval {
+    #[pyo3(get)]
+    start: f64,
+    #[pyo3(get)]
+    end: f64,
+}
+
+/// A summary of an instrument, with the General MIDI family of its program.
+#[pyclass]
+#[derive(Clone)]
+struct InstrumentStats {
+    #[pyo3(get)]
+    program: u8,
+    #[pyo3(get)]
+    is_drum: bool,
+    #[pyo3(get)]
+    name: String,
+    #[pyo3(get)]
+    family: &'static str,
+    #[pyo3(get)]
+    note_count: usize,
+}
+
+/// A summary of the notes of a file, see `MidiObject.stats`.
+#[pyclass]
+struct Stats {
+    #[pyo3(get)]
+    duration: f64,
+    #[pyo3(get)]
+    note_count: usize,
+    #[pyo3(get)]
+    max_polyphony: usize,
+    #[pyo3(get)]
+    mean_polyphony: f64,
+    #[pyo3(get)]
+    pitch_range: Option<(u8, u8)>,
+    #[pyo3(get)]
+    velocity_mean: f64,
+    #[pyo3(get)]
+    velocity_std: f64,
+    #[pyo3(get)]
+    velocity_range: Option<(u8, u8)>,
+    #[pyo3(get)]
+    velocity_histogram: Vec<usize>,
+    #[pyo3(get)]
+    note_density: Vec<f64>,
+    #[pyo3(get)]
+    tempo_changes: usize,
+    #[pyo3(get)]
+    instruments: Vec<InstrumentStats>,
+    #[pyo3(get)]
+    drum_ratio: f64,
+}
+
+impl From<midi::Stats> for Stats {
+    fn from(stats: midi::Stats) -> Self {
+        Stats {
+            duration: stats.duration,
+            note_count: stats.note_count,
+            max_polyphony: stats.max_polyphony,
+            mean_polyphony: stats.mean_polyphony,
+            pitch_range: stats.pitch_range,
+            velocity_mean: stats.velocity_mean,
+            velocity_std: stats.velocity_std,
+            velocity_range: stats.velocity_range,
+            velocity_histogram: stats.velocity_histogram,
+            note_density: stats.note_density,
+            tempo_changes: stats.tempo_changes,
+            instruments: stats.instruments
+                .into_iter()
+                .map(|instrument| InstrumentStats {
+                    program: instrument.program,
+                    is_drum: instrument.is_drum,
+                    name: instrument.name,
+                    family: instrument.family,
+                    note_count: instrument.note_count,
+                })
+                .collect(),
+            drum_ratio: stats.drum_ratio,
+        }
+    }
+}
+
+/// A key estimated from the notes, with the correlation of their pitch classes with its profile.
+#[pyclass]
+#[derive(Clone)]
+struct KeyEstimate {
+    #[pyo3(get)]
+    key_number: u8,
+    #[pyo3(get)]
+    key_name: String,
+    #[pyo3(get)]
+    correlation: f64,
+}
+
+impl From<midi::KeyEstimate> for KeyEstimate {
+    fn from(estimate: midi::KeyEstimate) -> Self {
+        KeyEstimate {
+            key_number: estimate.key_number,
+            key_name: midi::key_number_to_key_name(estimate.key_number),
+            correlation: estimate.correlation,
+        }
+    }
+}
+
+/// The chord sounding between `start` and `end`. Without any pitched notes the chord is 'N',
+/// like in Harte's chord syntax, and its root, quality and bass are None.
+#[pyclass]
+#[derive(Clone)]
+struct Chord {
+    #[pyo3(get)]
+    start: f64,
+    #[pyo3(get)]
+    end: f64,
+    /// The pitch class of the root.
+    #[pyo3(get)]
+    root: Option<u8>,
+    /// The quality in the shorthand of Harte's chord syntax, like 'min7'.
+    #[pyo3(get)]
+    quality: Option<&'static str>,
+    /// The pitch class of the lowest note.
+    #[pyo3(get)]
+    bass: Option<u8>,
+    /// The lead sheet symbol, like 'Am7' or 'C/E'.
+    #[pyo3(get)]
+    symbol: String,
+}
+
+impl From<midi::ChordSegment> for Chord {
+    fn from(segment: midi::ChordSegment) -> Self {
+        Chord {
+            start: segment.start,
+            end: segment.end,
+            root: segment.chord.map(|chord| chord.root),
+            quality: segment.chord.map(|chord| chord.quality.shorthand()),
+            bass: segment.chord.map(|chord| chord.bass),
+            symbol: segment.chord.map(|chord| chord.symbol()).unwrap_or_else(|| "N".to_string()),
+        }
+    }
+}
+
+/// A score note and the performance note played for it, each with the index of its instrument
+/// and its index within the notes of that instrument.
+#[pyclass]
+#[derive(Clone)]
+struct AlignedNote {
+    #[pyo3(get)]
+    pitch: u8,
+    #[pyo3(get)]
+    score_instrument: usize,
+    #[pyo3(get)]
+    score_note: usize,
+    #[pyo3(get)]
+    score_start: f64,
+    #[pyo3(get)]
+    score_end: f64,
+    #[pyo3(get)]
+    performance_instrument: usize,
+    #[pyo3(get)]
+    performance_note: usize,
+    #[pyo3(get)]
+    performance_start: f64,
+    #[pyo3(get)]
+    performance_end: f64,
+    /// The velocity of the performance note.
+    #[pyo3(get)]
+    velocity: u8,
+}
+
+/// The alignment of a performance to a score.
+#[pyclass]
+struct Alignment {
+    /// The matched notes, ordered by the score.
+    #[pyo3(get)]
+    notes: Vec<AlignedNote>,
+    /// The warping path as pairs of a score and a performance time.
+    #[pyo3(get)]
+    path: Vec<(f64, f64)>,
+    /// The tempo of the performance in beats of the score per minute, as pairs of the performance time
+    /// it starts at and the tempo.
+    #[pyo3(get)]
+    tempo_curve: Vec<(f64, f64)>,
+    warp: midi::TimeWarp,
+}
+
+#[pymethods]
+impl Alignment {
+    /// Maps a time in the score onto the performance along the warping path.
+    fn score_to_performance_time(&self, time: f64) -> f64 {
+        self.warp.apply(time)
+    }
+}
+
+/// Options controlling how a file is interpreted when it is loaded.
+struct LoadOptions {
+    pairing: midi::NotePairing,
+    /// Applies the sustain pedal with the given threshold to every instrument.
+    sustain_threshold: Option<u8>,
+    encoding: midi::TextEncoding,
+}
+
+impl LoadOptions {
+    fn new(note_pairing: Option<&str>, sustain_threshold: Option<u8>, charset: Option<&str>) -> Result<Self, Error> {
+        Ok(LoadOptions {
+            pairing: parse_note_pairing(note_pairing.unwrap_or("pretty_midi"))?,
+            sustain_threshold,
+            encoding: parse_charset(charset.unwrap_or("utf-8"))?,
+        })
+    }
+}
+
+fn parse_charset(name: &str) -> Result<midi::TextEncoding, Error> {
+    match name.to_lowercase().replace('_', "-").as_str() {
+        "utf-8" | "utf8" => Ok(midi::TextEncoding::Utf8),
+        "latin1" | "latin-1" | "iso-8859-1" => Ok(midi::TextEncoding::Latin1),
+        _ => Err(Error::value(format!(
+            "unknown charset '{}', expected either 'utf-8' or 'latin1'",
+            name
+        ))),
+    }
+}
+
+fn parse_note_pairing(name: &str) -> Result<midi::NotePairing, Error> {
+    match name {
+        "pretty_midi" => Ok(midi::NotePairing::PrettyMidi),
+        "fifo" => Ok(midi::NotePairing::Fifo),
+        "lifo" => Ok(midi::NotePairing::Lifo),
+        "close_all" => Ok(midi::NotePairing::CloseAll),
+        _ => Err(Error::value(format!(
+            "unknown note pairing '{}', expected one of 'pretty_midi', 'fifo', 'lifo' or 'close_all'",
+            name
+        ))),
+    }
+}
+
+fn parse_pitch_overflow(name: &str) -> Result<midi::PitchOverflow, Error> {
+    match name {
+        "drop" => Ok(midi::PitchOverflow::Drop),
+        "clamp" => Ok(midi::PitchOverflow::Clamp),
+        "fold" => Ok(midi::PitchOverflow::Fold),
+        _ => Err(Error::value(format!(
+            "unknown out of range policy '{}', expected one of 'drop', 'clamp' or 'fold'",
+            name
+        ))),
+    }
+}
+
+fn parse_key_profile(name: &str) -> Result<midi::KeyProfile, Error> {
+    match name {
+        "krumhansl" | "krumhansl_schmuckler" => Ok(midi::KeyProfile::KrumhanslSchmuckler),
+        "temperley" => Ok(midi::KeyProfile::Temperley),
+        "albrecht" => Ok(midi::KeyProfile::Albrecht),
+        _ => Err(Error::value(format!(
+            "unknown key profile '{}', expected one of 'krumhansl', 'temperley' or 'albrecht'",
+            name
+        ))),
+    }
+}
+
+fn parse_melody_strategy(name: &str) -> Result<midi::MelodyStrategy, Error> {
+    match name {
+        "skyline" => Ok(midi::MelodyStrategy::Skyline),
+        "track" => Ok(midi::MelodyStrategy::Track),
+        "voice" => Ok(midi::MelodyStrategy::Voice),
+        _ => Err(Error::value(format!(
+            "unknown melody strategy '{}', expected one of 'skyline', 'track' or 'voice'",
+            name
+        ))),
+    }
+}
+
+/// Reads a key given either as a key number or as a name like `"Bb minor"`.
+fn extract_key_number(key: &PyAny) -> PyResult<u8> {
+    let key_number = match key.extract::<u8>() {
+        Ok(key_number) => Some(key_number).filter(|key_number| *key_number < 24),
+        Err(_) => midi::key_name_to_key_number(key.extract()?),
+    };
+    key_number.ok_or_else(|| Error::value(format!("invalid key {}", key)).into())
+}
+
+/// Reads a grid given as a note value, either as a fraction of a whole note like `0.0625`
+/// or a name like `"1/16"`, `"1/8t"` for triplets or `"1/4d"` for dotted notes.
+fn extract_grid(grid: &PyAny, swing: Option<f64>) -> PyResult<midi::Grid> {
+    let beats = match grid.extract::<f64>() {
+        Ok(length) => Some(length * 4.0).filter(|beats| beats.is_finite() && *beats > 0.0),
+        Err(_) => midi::note_value_in_beats(grid.extract()?),
+    };
+    let beats = beats.ok_or_else(|| Error::value(format!("invalid grid {}", grid)))?;
+
+    let swing = swing.unwrap_or(0.0);
+    if !(0.0..1.0).contains(&swing) {
+        return Err(Error::value("the swing has to be at least 0 and below 1").into());
+    }
+    Ok(midi::Grid::new(beats, swing))
+}
+
+/// A note with its start and end meassured in quarter notes.
+#[pyclass]
+#[derive(Clone)]
+struct BeatNote {
+    #[pyo3(get)]
+    pitch: u8,
+    #[pyo3(get)]
+    velocity: u8,
+    #[pyo3(get)]
+    start: f64,
+    #[pyo3(get)]
+    end: f64,
+}
+
+/// A note with its start and end meassured in MIDI ticks.
+#[pyclass]
+#[derive(Clone)]
+struct TickNote {
+    #[pyo3(get)]
+    pitch: u8,
+    #[pyo3(get)]
+    velocity: u8,
+    #[pyo3(get)]
+    start: u32,
+    #[pyo3(get)]
+    end: u32,
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct TickPitchBend {
+    #[pyo3(get)]
+    pitch: i16,
+    #[pyo3(get)]
+    time: u32,
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct TickControlChange {
+    #[pyo3(get)]
+    number: u8,
+    #[pyo3(get)]
+    value: u8,
+    #[pyo3(get)]
+    time: u32,
+}
+
+/// An instrument with all of its events timed in MIDI ticks.
+#[pyclass]
+#[derive(Clone)]
+struct TickInstrument {
+    #[pyo3(get)]
+    program: u8,
+    #[pyo3(get)]
+    is_drum: bool,
+    #[pyo3(get)]
+    name: String,
+    #[pyo3(get)]
+    notes: Vec<TickNote>,
+    #[pyo3(get)]
+    pitch_bends: Vec<TickPitchBend>,
+    #[pyo3(get)]
+    control_changes: Vec<TickControlChange>,
+}
+
+impl From<midi::Instrument<midi::TickTime>> for TickInstrument {
+    fn from(instrument: midi::Instrument<midi::TickTime>) -> Self {
+        TickInstrument {
+            notes: instrument.notes
+                .iter()
+                .map(|note| TickNote {
+                    pitch: note.pitch,
+                    velocity: note.velocity,
+                    start: note.start_time,
+                    end: note.end_time,
+                })
+                .collect(),
+            pitch_bends: instrument.pitch_bends
+                .iter()
+                .map(|bend| TickPitchBend { pitch: bend.pitch(), time: bend.time })
+                .collect(),
+            control_changes: instrument.control_changes
+                .iter()
+                .map(|cc| TickControlChange { number: cc.number, value: cc.value, time: cc.time })
+                .collect(),
+            program: instrument.program,
+            is_drum: instrument.is_drum,
+            name: instrument.name,
+        }
+    }
+}
+
+/// The instruments of a file with time meassured in MIDI ticks.
+#[pyclass]
+struct TickMidiObject {
+    #[pyo3(get)]
+    resolution: u16,
+    #[pyo3(get)]
+    instruments: Vec<TickInstrument>,
+}
+
+/// An instrument with its events timed in seconds. Instruments read from a file also keep
+/// the exact ticks of their events, until they are changed.
+#[pyclass]
+#[derive(Clone)]
+struct Instrument(
+    Arc<midi::Instrument<midi::RealTime>>,
+    Arc<midi::TickConverter>,
+    Option<Arc<midi::Instrument<midi::TickTime>>>,
+);
+
+impl Instrument {
+    /// Gives mutable access to the data, dropping the ticks it no longer matches.
+    fn data_mut(&mut self) -> &mut midi::Instrument<midi::RealTime> {
+        self.2 = None;
+        Arc::make_mut(&mut self.0)
+    }
+}
 
 #[pymethods]
 impl Instrument {
@@ -139,54 +951,947 @@ impl Instrument {
     fn program(&self) -> u8 {
         self.0.program
     }
+
+    #[getter]
+    fn is_drum(&self) -> bool {
+        self.0.is_drum
+    }
+
+    #[getter]
+    fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    #[getter]
+    fn pitch_bends(&self) -> Vec<PitchBend> {
+        self.0.pitch_bends.iter().map(PitchBend::from).collect()
+    }
+
+    #[getter]
+    fn control_changes(&self) -> Vec<ControlChange> {
+        self.0.control_changes.iter().map(ControlChange::from).collect()
+    }
+
+    /// Extends the notes released while the sustain pedal (CC 64) is held down until it is lifted.
+    /// Notes sounding when the sostenuto pedal (CC 66) is pressed are held until it is lifted.
+    /// A pedal counts as held down while its value is at least `threshold`, which defaults to 64.
+    fn apply_sustain_pedal(&mut self, threshold: Option<u8>) {
+        self.data_mut().apply_sustain_pedal(threshold.unwrap_or(DEFAULT_PEDAL_THRESHOLD));
+    }
+
+    /// Removes the notes that end before or at the same time as they start.
+    fn remove_invalid_notes(&mut self) {
+        self.data_mut().remove_invalid_notes();
+    }
+
+    /// Merges duplicate notes, truncates notes overlapping a later note of the same pitch,
+    /// drops notes without a duration and clamps the notes to `end_time`, when given.
+    fn clean(&mut self, end_time: Option<f64>) {
+        self.data_mut().clean(end_time);
+    }
+
+    /// Moves every note by a number of semitones, leaving drum instruments as they are.
+    /// Notes moved outside of the MIDI range are handled by `out_of_range`,
+    /// which either drops them, clamps them to the range or folds them back by octaves,
+    /// given as 'drop', the default, 'clamp' or 'fold'.
+    fn transpose(&mut self, semitones: i32, out_of_range: Option<&str>) -> PyResult<()> {
+        let overflow = parse_pitch_overflow(out_of_range.unwrap_or("drop"))?;
+        self.data_mut().transpose(semitones, overflow);
+        Ok(())
+    }
+
+    /// Moves every note by a number of steps along the scale of `key`, see `transpose`.
+    /// The key is either a key number or a name like 'Bb minor' and defaults to C major.
+    fn transpose_diatonic(&mut self, steps: i32, key: Option<&PyAny>, out_of_range: Option<&str>) -> PyResult<()> {
+        let overflow = parse_pitch_overflow(out_of_range.unwrap_or("drop"))?;
+        let key_number = key.map(extract_key_number).transpose()?.unwrap_or(0);
+        let keys = [midi::KeySignature { key_number, time: 0.0 }];
+        self.data_mut().transpose_diatonic(steps, &keys, overflow);
+        Ok(())
+    }
+
+    /// Returns the distinct start times of the notes in seconds, in order.
+    fn get_onsets(&self) -> Vec<f64> {
+        self.0.onsets()
+    }
+
+    /// Returns the time in seconds of the last note end, pitch bend or control change.
+    fn get_end_time(&self) -> f64 {
+        self.0.end_time()
+    }
+
+    /// Returns the number of notes of each of the 12 pitch classes, weighting the notes
+    /// by their duration and velocity when asked to. When `normalize` is true the histogram sums to one.
+    /// Drum instruments have an empty histogram.
+    fn get_pitch_class_histogram(
+        &self,
+        use_duration: Option<bool>,
+        use_velocity: Option<bool>,
+        normalize: Option<bool>,
+    ) -> Vec<f64> {
+        self.0
+            .pitch_class_histogram(
+                use_duration.unwrap_or(false),
+                use_velocity.unwrap_or(false),
+                normalize.unwrap_or(false),
+            )
+            .to_vec()
+    }
+
+    /// Returns the 12 by 12 matrix counting the transitions from one pitch class to another,
+    /// where a note ending less than `time_thresh` seconds, 0.05 by default, from the start of another
+    /// counts as a transition. When `normalize` is true the matrix sums to one.
+    fn get_pitch_class_transition_matrix(&self, normalize: Option<bool>, time_thresh: Option<f64>) -> Vec<Vec<f64>> {
+        self.0
+            .pitch_class_transition_matrix(
+                normalize.unwrap_or(false),
+                time_thresh.unwrap_or(DEFAULT_TRANSITION_THRESHOLD),
+            )
+            .iter()
+            .map(|row| row.to_vec())
+            .collect()
+    }
+
+    /// Snaps the notes to a grid following the tempo map. The `grid` is a note value,
+    /// either a fraction of a whole note like `1/16` or a name like '1/16', '1/8t' for triplets
+    /// or '1/4d' for dotted notes. A `strength` below one moves the notes only part of the way
+    /// and `swing` delays every second position of the grid by that fraction of a step.
+    /// Unless `quantize_ends` is false, the ends of the notes are snapped as well,
+    /// otherwise the notes keep their duration.
+    fn quantize(
+        &mut self,
+        grid: &PyAny,
+        strength: Option<f64>,
+        swing: Option<f64>,
+        quantize_ends: Option<bool>,
+    ) -> PyResult<()> {
+        let grid = extract_grid(grid, swing)?;
+        let strength = strength.unwrap_or(1.0);
+        if !(0.0..=1.0).contains(&strength) {
+            return Err(Error::value("the strength has to be between 0 and 1").into());
+        }
+
+        let converter = self.1.clone();
+        self.data_mut().quantize(&grid, &converter, strength, quantize_ends.unwrap_or(true));
+        Ok(())
+    }
+
+    /// Returns the part of the instrument between `start` and `end` in seconds, moved to start at zero.
+    /// Notes sounding at the edges are shortened to fit, unless `clip_notes` is false,
+    /// in which case only the notes lying entirely within the range are kept.
+    /// The pitch bend and controller values at `start` are carried in.
+    fn slice(&self, start: f64, end: f64, clip_notes: Option<bool>) -> PyResult<Instrument> {
+        check_slice_range(start, end)?;
+        Ok(Instrument(
+            Arc::new(self.0.slice(start, end, clip_notes.unwrap_or(true))),
+            Arc::new(self.1.slice(start)),
+            None,
+        ))
+    }
+
+    /// Splits the notes into monophonic voices, at most `max_voices` of them when given,
+    /// continuing each voice with the notes closest in pitch and time while avoiding crossings.
+    /// A new voice only starts when all voices are sounding and notes continuing a voice
+    /// cut the previous note short. Notes that fit in no voice are left out.
+    /// Returns the voices from the highest to the lowest as instruments like this one.
+    fn separate_voices(&self, max_voices: Option<usize>) -> PyResult<Vec<Instrument>> {
+        if max_voices == Some(0) {
+            return Err(Error::value("there has to be at least one voice").into());
+        }
+
+        Ok(self
+            .0
+            .separate_voices(max_voices.unwrap_or(usize::MAX))
+            .into_iter()
+            .map(|voice| Instrument(Arc::new(voice), self.1.clone(), None))
+            .collect())
+    }
+
+    /// Returns the intervals during which the given pedal, the sustain pedal by default, is held down.
+    fn get_pedal_intervals(&self, number: Option<u8>, threshold: Option<u8>) -> Vec<PedalInterval> {
+        self.0
+            .pedal_intervals(
+                number.unwrap_or(midi::SUSTAIN_PEDAL),
+                threshold.unwrap_or(DEFAULT_PEDAL_THRESHOLD),
+            )
+            .into_iter()
+            .map(|interval| PedalInterval { start: interval.start, end: interval.end })
+            .collect()
+    }
+
+    /// Returns the instrument with all of its events timed in MIDI ticks.
+    /// These are the ticks of the file for instruments read from one and left unchanged,
+    /// otherwise the times in seconds are rounded to the nearest tick.
+    fn to_ticks(&self) -> TickInstrument {
+        match &self.2 {
+            Some(ticks) => midi::Instrument::clone(ticks).into(),
+            None => midi::Instrument::clone(&self.0).into_tick_time(&self.1).into(),
+        }
+    }
+
+    /// Returns the notes with their start and end meassured in quarter notes
+    /// rather than seconds, which makes them independent of tempo and resolution.
+    fn notes_in_beats(&self) -> Vec<BeatNote> {
+        midi::Instrument::clone(&self.0)
+            .into_beat_time(&self.1)
+            .notes
+            .into_iter()
+            .map(|note| BeatNote {
+                pitch: note.pitch,
+                velocity: note.velocity,
+                start: note.start_time,
+                end: note.end_time,
+            })
+            .collect()
+    }
     
 }
 
 #[pyclass]
 struct MidiObject {
-    #[pyo3(get, set)]
-    resolution: u16,
     #[pyo3(get)]
-    instruments: Vec<Instrument>,
+    instruments: Vec<Py<Instrument>>,
+    #[pyo3(get)]
+    key_signature_changes: Vec<KeySignature>,
+    #[pyo3(get)]
+    time_signature_changes: Vec<TimeSignature>,
+    #[pyo3(get)]
+    lyrics: Vec<Lyric>,
+    #[pyo3(get)]
+    text_events: Vec<Text>,
+    #[pyo3(get)]
+    dangling_notes: Vec<DanglingNote>,
+    end_of_file: f64,
+    converter: Arc<midi::TickConverter>,
+    meter: midi::MeterConverter,
 }
 
 #[pymethods]
 impl MidiObject {
     #[new]
-    fn new(file_path: Option<&str>, resolution: Option<u16>, initial_tempo: Option<u32>) -> PyResult<Self> {
-        let resolution = resolution.unwrap_or(220);
-        let initial_tempo = initial_tempo.unwrap_or(120);
+    fn new(
+        py: Python<'_>,
+        file_path: Option<&str>,
+        resolution: Option<u16>,
+        initial_tempo: Option<f64>,
+        note_pairing: Option<&str>,
+        sustain_threshold: Option<u8>,
+        charset: Option<&str>,
+    ) -> PyResult<Self> {
+        let resolution = resolution.unwrap_or(midi::DEFAULT_RESOLUTION);
+        let initial_tempo = match initial_tempo {
+            Some(bpm) if !(bpm.is_finite() && bpm > 0.0) => {
+                return Err(Error::value(format!("invalid initial tempo {} bpm", bpm)).into())
+            }
+            // Microseconds per beat, giving `60 / (bpm * resolution)` seconds per tick like `PrettyMIDI`
+            Some(bpm) => (6e7 / bpm).round() as u32,
+            None => midi::DEFAULT_TEMPO,
+        };
+        let options = LoadOptions::new(note_pairing, sustain_threshold, charset)?;
 
         if let Some(path) = file_path {
-            MidiObject::from_file(path)
+            MidiObject::from_file(py, path, &options)
         } else {
             Ok(MidiObject {
-                resolution,
                 instruments: vec![],
+                key_signature_changes: vec![],
+                time_signature_changes: vec![],
+                lyrics: vec![],
+                text_events: vec![],
+                dangling_notes: vec![],
+                end_of_file: 0.0,
+                converter: Arc::new(midi::TickConverter::new(
+                    &[(0, initial_tempo)],
+                    resolution,
+                )),
+                meter: midi::MeterConverter::new(&[], resolution),
+            })
+        }
+    }
+
+    /// Parses a MIDI file from an in-memory buffer.
+    #[staticmethod]
+    fn from_bytes(
+        py: Python<'_>,
+        data: &[u8],
+        note_pairing: Option<&str>,
+        sustain_threshold: Option<u8>,
+        charset: Option<&str>,
+    ) -> PyResult<Self> {
+        let options = LoadOptions::new(note_pairing, sustain_threshold, charset)?;
+        MidiObject::from_data(py, data, &options)
+    }
+
+    /// Returns the instruments with all of their events timed in MIDI ticks,
+    /// avoiding the rounding of times in seconds.
+    fn to_ticks(&self, py: Python<'_>) -> TickMidiObject {
+        TickMidiObject {
+            resolution: self.converter.resolution(),
+            instruments: self.instruments
+                .iter()
+                .map(|instrument| instrument.borrow(py).to_ticks())
+                .collect(),
+        }
+    }
+
+    /// Applies the sustain and sostenuto pedals to every instrument, see `Instrument.apply_sustain_pedal`.
+    fn apply_sustain_pedal(&self, py: Python<'_>, threshold: Option<u8>) {
+        for instrument in &self.instruments {
+            instrument.borrow_mut(py).apply_sustain_pedal(threshold);
+        }
+    }
+
+    /// Removes the notes that end before or at the same time as they start from every instrument.
+    fn remove_invalid_notes(&self, py: Python<'_>) {
+        for instrument in &self.instruments {
+            instrument.borrow_mut(py).remove_invalid_notes();
+        }
+    }
+
+    /// Cleans up every instrument, see `Instrument.clean`,
+    /// clamping the notes to the last event of the file.
+    fn clean(&self, py: Python<'_>) {
+        for instrument in &self.instruments {
+            instrument.borrow_mut(py).clean(Some(self.end_of_file));
+        }
+    }
+
+    /// Warps the timing of all events so that the `original_times` are moved to the `new_times`,
+    /// interpolating linearly in between, like `PrettyMIDI.adjust_times`.
+    /// Notes not lying entirely within the original times are removed, dangling notes are moved
+    /// like the other events and the tempo map is rewritten to follow the new timing.
+    fn adjust_times(&mut self, py: Python<'_>, original_times: Vec<f64>, new_times: Vec<f64>) -> PyResult<()> {
+        if original_times.len() != new_times.len() {
+            return Err(Error::value("original_times and new_times must have the same length").into());
+        }
+        if original_times.len() < 2 {
+            return Err(Error::value("at least two times are needed to adjust the timing").into());
+        }
+        if original_times.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(Error::value("original_times must be strictly increasing").into());
+        }
+        if new_times.windows(2).any(|w| w[0] > w[1]) || new_times[0] < 0.0 {
+            return Err(Error::value("new_times must be non-negative and non-decreasing").into());
+        }
+        let warp = midi::TimeWarp::new(original_times, new_times);
+
+        let converter = Arc::new(self.converter.adjust_times(&warp));
+        for instrument in &self.instruments {
+            let mut instrument = instrument.borrow_mut(py);
+            instrument.data_mut().adjust_times(&warp);
+            instrument.1 = converter.clone();
+        }
+
+        let mut metadata = self.metadata();
+        metadata.adjust_times(&warp);
+        self.set_metadata(metadata);
+        midi::adjust_event_times(&mut self.dangling_notes, |note| &mut note.start, &warp);
+        self.end_of_file = warp.apply(self.end_of_file);
+
+        self.meter = meter_converter(&self.time_signature_changes, &converter);
+        self.converter = converter;
+        Ok(())
+    }
+
+    /// Returns the distinct start times of the notes of all instruments in seconds, in order.
+    fn get_onsets(&self, py: Python<'_>) -> Vec<f64> {
+        let mut onsets: Vec<_> = self.instruments
+            .iter()
+            .flat_map(|instrument| instrument.borrow(py).get_onsets())
+            .collect();
+        onsets.sort_by(f64::total_cmp);
+        onsets.dedup();
+        onsets
+    }
+
+    /// Returns the time in seconds of the last event, like `PrettyMIDI.get_end_time`,
+    /// considering the instruments, tempo changes, key and time signatures, lyrics and text events.
+    fn get_end_time(&self, py: Python<'_>) -> f64 {
+        self.instruments
+            .iter()
+            .map(|instrument| instrument.borrow(py).get_end_time())
+            .chain(self.converter.tempo_changes().into_iter().map(|(time, _)| time))
+            .chain(self.key_signature_changes.iter().map(|ks| ks.time))
+            .chain(self.time_signature_changes.iter().map(|ts| ts.time))
+            .chain(self.lyrics.iter().map(|lyric| lyric.time))
+            .chain(self.text_events.iter().map(|text| text.time))
+            .fold(0.0, f64::max)
+    }
+
+    /// Summarizes the notes of the file: its duration and number of notes, the polyphony,
+    /// pitch range and velocities, the number of notes starting per second within windows
+    /// of `density_window` seconds, one by default, the number of tempos and the instruments
+    /// with their General MIDI family. Drums are left out of the polyphony and pitch range.
+    /// The velocity histogram counts the notes within each range of 16 velocities.
+    fn stats(&self, py: Python<'_>, density_window: Option<f64>) -> PyResult<Stats> {
+        let window = density_window.unwrap_or(1.0);
+        if window <= 0.0 {
+            return Err(Error::value("the density window has to be positive").into());
+        }
+
+        let instruments = self.instrument_data(py);
+        let tempo_changes = self.converter.tempo_changes().len();
+        Ok(midi::Stats::new(&instruments, self.get_end_time(py), tempo_changes, window).into())
+    }
+
+    /// Estimates the key from how long the notes of each pitch class sound, leaving out drums,
+    /// by correlating them with the key profiles given by `profile`: 'krumhansl', the default,
+    /// for the Krumhansl–Schmuckler algorithm, 'temperley' or 'albrecht'.
+    /// Returns None for files without pitched notes.
+    fn estimate_key(&self, py: Python<'_>, profile: Option<&str>) -> PyResult<Option<KeyEstimate>> {
+        let profile = parse_key_profile(profile.unwrap_or("krumhansl"))?;
+        let instruments = self.instrument_data(py);
+        Ok(midi::estimate_key_between(&instruments, 0.0, f64::INFINITY, profile).map(KeyEstimate::from))
+    }
+
+    /// Estimates the key within windows of `window` seconds, 8 by default, starting every `hop` seconds,
+    /// half a window by default, see `estimate_key`. Returns `(start, key)` pairs,
+    /// where the key is None for windows without pitched notes.
+    fn estimate_key_curve(
+        &self,
+        py: Python<'_>,
+        window: Option<f64>,
+        hop: Option<f64>,
+        profile: Option<&str>,
+    ) -> PyResult<Vec<(f64, Option<KeyEstimate>)>> {
+        let profile = parse_key_profile(profile.unwrap_or("krumhansl"))?;
+        let window = window.unwrap_or(8.0);
+        let hop = hop.unwrap_or(window / 2.0);
+        if window <= 0.0 || hop <= 0.0 {
+            return Err(Error::value("the window and hop have to be positive").into());
+        }
+
+        let instruments = self.instrument_data(py);
+        let end = self.get_end_time(py);
+        Ok(midi::estimate_key_curve(&instruments, window, hop, end, profile)
+            .into_iter()
+            .map(|(start, key)| (start, key.map(KeyEstimate::from)))
+            .collect())
+    }
+
+    /// Labels every beat, or every bar when `resolution` is 'bar', with the chord that best matches
+    /// the pitch content of the pitched instruments. Beats and bars follow the tempo map
+    /// and time signatures, with beats measured in the note value of the denominator.
+    fn estimate_chords(&self, py: Python<'_>, resolution: Option<&str>) -> PyResult<Vec<Chord>> {
+        let end = self.get_end_time(py);
+        let end_tick = self.converter.time_to_beat(end) * self.converter.resolution() as f64;
+        let ticks = match resolution.unwrap_or("beat") {
+            "beat" => self.meter.beat_ticks(end_tick),
+            "bar" => self.meter.measure_ticks(end_tick),
+            resolution => {
+                return Err(Error::value(format!(
+                    "unknown chord resolution '{}', expected either 'beat' or 'bar'",
+                    resolution
+                ))
+                .into())
+            }
+        };
+
+        let mut boundaries: Vec<_> = ticks
+            .into_iter()
+            .map(|tick| self.converter.beat_to_time(tick / self.converter.resolution() as f64))
+            .collect();
+        boundaries.push(end);
+
+        Ok(midi::estimate_chords(&self.instrument_data(py), &boundaries)
+            .into_iter()
+            .map(Chord::from)
+            .collect())
+    }
+
+    /// Returns the melody as a monophonic instrument, found with the given `strategy`:
+    ///  - 'skyline', the default, takes the highest note sounding at any time from all pitched instruments,
+    ///  - 'track' takes the instrument that looks most like a melody, judging by its name, polyphony,
+    ///    register and range, and reduces it to its highest notes,
+    ///  - 'voice' separates the pitched notes into voices with few crossings and takes the highest one.
+    fn extract_melody(&self, py: Python<'_>, strategy: Option<&str>) -> PyResult<Instrument> {
+        let strategy = parse_melody_strategy(strategy.unwrap_or("skyline"))?;
+        let melody = midi::extract_melody(&self.instrument_data(py), strategy);
+        Ok(Instrument(Arc::new(melody), self.converter.clone(), None))
+    }
+
+    /// Aligns this performance to a `score`, matching the notes of its pitched instruments to those
+    /// of the score. The chroma of both, in frames of `hop` seconds, 0.1 by default, are aligned by dynamic
+    /// time warping, whose memory and time grow with the product of their lengths. Every score note is then
+    /// matched to a performance note of the same pitch starting at most `tolerance` seconds, 0.5 by default,
+    /// from where the warping path puts it. The tempo curve follows the matched notes, counting the beats
+    /// of the score by its tempo map, and passes over notes played out of order.
+    fn align_to_score(
+        &self,
+        py: Python<'_>,
+        score: PyRef<MidiObject>,
+        hop: Option<f64>,
+        tolerance: Option<f64>,
+    ) -> PyResult<Alignment> {
+        let hop = hop.unwrap_or(0.1);
+        let tolerance = tolerance.unwrap_or(0.5);
+        if hop <= 0.0 || tolerance < 0.0 {
+            return Err(Error::value("the hop has to be positive and the tolerance can not be negative").into());
+        }
+
+        let (score_notes, score_indices) = score.pitched_notes(py);
+        let (notes, indices) = self.pitched_notes(py);
+        let alignment = midi::align(&score_notes, &notes, &score.converter, hop, tolerance);
+
+        Ok(Alignment {
+            notes: alignment
+                .notes
+                .iter()
+                .map(|(s, p)| {
+                    let (score_note, note) = (&score_notes[*s], &notes[*p]);
+                    AlignedNote {
+                        pitch: score_note.pitch,
+                        score_instrument: score_indices[*s].0,
+                        score_note: score_indices[*s].1,
+                        score_start: score_note.start_time,
+                        score_end: score_note.end_time,
+                        performance_instrument: indices[*p].0,
+                        performance_note: indices[*p].1,
+                        performance_start: note.start_time,
+                        performance_end: note.end_time,
+                        velocity: note.velocity,
+                    }
+                })
+                .collect(),
+            path: alignment.path,
+            tempo_curve: alignment.tempo_curve,
+            warp: alignment.warp,
+        })
+    }
+
+    /// Returns the pitch class histogram of all instruments together, see `Instrument.get_pitch_class_histogram`.
+    fn get_pitch_class_histogram(
+        &self,
+        py: Python<'_>,
+        use_duration: Option<bool>,
+        use_velocity: Option<bool>,
+        normalize: Option<bool>,
+    ) -> Vec<f64> {
+        let mut histogram = [0.0; 12];
+        for instrument in &self.instruments {
+            let counts = instrument.borrow(py).0.pitch_class_histogram(
+                use_duration.unwrap_or(false),
+                use_velocity.unwrap_or(false),
+                false,
+            );
+            histogram.iter_mut().zip(counts).for_each(|(total, count)| *total += count);
+        }
+
+        if normalize.unwrap_or(false) {
+            midi::normalize_histogram(&mut histogram);
+        }
+        histogram.to_vec()
+    }
+
+    /// Returns the pitch class transitions of all instruments together,
+    /// see `Instrument.get_pitch_class_transition_matrix`.
+    fn get_pitch_class_transition_matrix(
+        &self,
+        py: Python<'_>,
+        normalize: Option<bool>,
+        time_thresh: Option<f64>,
+    ) -> Vec<Vec<f64>> {
+        let mut matrix = [[0.0; 12]; 12];
+        for instrument in &self.instruments {
+            let counts = instrument.borrow(py).0.pitch_class_transition_matrix(
+                false,
+                time_thresh.unwrap_or(DEFAULT_TRANSITION_THRESHOLD),
+            );
+            matrix.iter_mut().flatten().zip(counts.iter().flatten()).for_each(|(total, count)| *total += count);
+        }
+
+        if normalize.unwrap_or(false) {
+            midi::normalize_transitions(&mut matrix);
+        }
+        matrix.iter().map(|row| row.to_vec()).collect()
+    }
+
+    /// Snaps the notes of every instrument to a grid following the tempo map, see `Instrument.quantize`.
+    fn quantize(
+        &self,
+        py: Python<'_>,
+        grid: &PyAny,
+        strength: Option<f64>,
+        swing: Option<f64>,
+        quantize_ends: Option<bool>,
+    ) -> PyResult<()> {
+        for instrument in &self.instruments {
+            instrument.borrow_mut(py).quantize(grid, strength, swing, quantize_ends)?;
+        }
+        Ok(())
+    }
+
+    /// Moves every note by a number of semitones, leaving drum instruments as they are,
+    /// see `Instrument.transpose`. The key signatures are moved along.
+    fn transpose(&mut self, py: Python<'_>, semitones: i32, out_of_range: Option<&str>) -> PyResult<()> {
+        for instrument in &self.instruments {
+            instrument.borrow_mut(py).transpose(semitones, out_of_range)?;
+        }
+        for ks in &mut self.key_signature_changes {
+            ks.key_number = midi::KeySignature { key_number: ks.key_number, time: ks.time }
+                .transpose(semitones)
+                .key_number;
+        }
+        Ok(())
+    }
+
+    /// Moves every note by a number of steps along the scale of the key signature in effect when it starts.
+    /// When `key` is given, either as a key number or a name like 'Bb minor', it is used instead.
+    /// Files without key signatures are treated as C major. See `Instrument.transpose` for `out_of_range`.
+    fn transpose_diatonic(
+        &self,
+        py: Python<'_>,
+        steps: i32,
+        key: Option<&PyAny>,
+        out_of_range: Option<&str>,
+    ) -> PyResult<()> {
+        let overflow = parse_pitch_overflow(out_of_range.unwrap_or("drop"))?;
+        let keys: Vec<_> = match key {
+            Some(key) => vec![midi::KeySignature { key_number: extract_key_number(key)?, time: 0.0 }],
+            None => self.key_signature_changes
+                .iter()
+                .map(|ks| midi::KeySignature { key_number: ks.key_number, time: ks.time })
+                .collect(),
+        };
+
+        for instrument in &self.instruments {
+            instrument.borrow_mut(py).data_mut().transpose_diatonic(steps, &keys, overflow);
+        }
+        Ok(())
+    }
+
+    /// Returns the part of the file between `start` and `end` in seconds, moved to start at zero,
+    /// see `Instrument.slice`. The tempo, key and time signature at `start` are carried in.
+    fn slice(&self, py: Python<'_>, start: f64, end: f64, clip_notes: Option<bool>) -> PyResult<MidiObject> {
+        check_slice_range(start, end)?;
+        let clip_notes = clip_notes.unwrap_or(true);
+        let converter = Arc::new(self.converter.slice(start));
+
+        let instruments = self.instruments
+            .iter()
+            .map(|instrument| {
+                let sliced = instrument.borrow(py).0.slice(start, end, clip_notes);
+                Py::new(py, Instrument(Arc::new(sliced), converter.clone(), None))
             })
+            .collect::<PyResult<_>>()?;
+
+        let mut sliced = MidiObject {
+            instruments,
+            key_signature_changes: vec![],
+            time_signature_changes: vec![],
+            lyrics: vec![],
+            text_events: vec![],
+            dangling_notes: midi::slice_event_times(&self.dangling_notes, |note| &mut note.start, start, end, false),
+            end_of_file: (self.end_of_file.min(end) - start).max(0.0),
+            meter: midi::MeterConverter::new(&[], converter.resolution()),
+            converter,
+        };
+        sliced.set_metadata(self.metadata().slice(start, end));
+        sliced.meter = meter_converter(&sliced.time_signature_changes, &sliced.converter);
+        Ok(sliced)
+    }
+
+    /// Returns a new object with the events of both files played at the same time.
+    /// The instruments sharing their program, drum flag and name are merged into one.
+    /// The tempo map and resolution are taken from this file.
+    fn merge(&self, py: Python<'_>, other: PyRef<MidiObject>) -> PyResult<MidiObject> {
+        let mut merged = self.combine(py, &other, 0.0, self.converter.clone())?;
+        merged.end_of_file = self.end_of_file.max(other.end_of_file);
+        Ok(merged)
+    }
+
+    /// Returns a new object with the events of `other` played after the end of this file,
+    /// followed by `gap` seconds of silence. The tempo map of `other` continues where it starts.
+    fn concat(&self, py: Python<'_>, other: PyRef<MidiObject>, gap: Option<f64>) -> PyResult<MidiObject> {
+        let gap = gap.unwrap_or(0.0);
+        if gap < 0.0 {
+            return Err(Error::value("the gap between two files can not be negative").into());
+        }
+
+        // Start on a tick, so the times of both files stay consistent with the tempo map
+        let resolution = self.converter.resolution() as f64;
+        let offset_tick = (self.converter.time_to_beat(self.end_of_file + gap) * resolution).ceil() as u32;
+        let offset = self.converter.tick_to_time(offset_tick);
+
+        let converter = Arc::new(self.converter.concat(&other.converter, offset_tick));
+        let mut concatenated = self.combine(py, &other, offset, converter)?;
+        concatenated.end_of_file = offset + other.end_of_file;
+        Ok(concatenated)
+    }
+
+    /// The number of ticks per beat. It is fixed by the file, as changing it would move every event.
+    #[getter]
+    fn resolution(&self) -> u16 {
+        self.converter.resolution()
+    }
+
+    /// The tick scales as `(tick, seconds per tick)` pairs, like `PrettyMIDI._tick_scales`.
+    #[getter]
+    fn tick_scales(&self) -> Vec<(u32, f64)> {
+        self.converter.tick_scales().to_vec()
+    }
+
+    /// Returns the tempo changes as a pair of lists, `(times, tempi)`,
+    /// with times in seconds and tempi in beats per minute.
+    fn get_tempo_changes(&self) -> (Vec<f64>, Vec<f64>) {
+        self.converter.tempo_changes().into_iter().unzip()
+    }
+
+    /// Converts a tick into seconds.
+    fn tick_to_time(&self, tick: u32) -> f64 {
+        self.converter.tick_to_time(tick)
+    }
+
+    /// Converts seconds into the nearest tick.
+    fn time_to_tick(&self, time: f64) -> u32 {
+        self.converter.time_to_tick(time)
+    }
+
+    /// Converts seconds into a, possibly fractional, number of quarter notes.
+    fn time_to_beat(&self, time: f64) -> f64 {
+        self.converter.time_to_beat(time)
+    }
+
+    /// Converts a, possibly fractional, number of quarter notes into seconds.
+    fn beat_to_time(&self, beat: f64) -> f64 {
+        self.converter.beat_to_time(beat)
+    }
+
+    /// Converts seconds into a DAW style `(bar, beat, tick)` position.
+    /// Bars and beats count from 1 and beats are measured in the note value of the time signature,
+    /// while the tick within the beat counts from 0 and may be fractional.
+    fn time_to_bbt(&self, time: f64) -> (u32, u32, f64) {
+        let tick = self.converter.time_to_beat(time) * self.converter.resolution() as f64;
+        let position = self.meter.tick_to_bbt(tick);
+        (position.bar, position.beat, position.tick)
+    }
+
+    /// Converts a DAW style `(bar, beat, tick)` position into seconds.
+    fn bbt_to_time(&self, bar: u32, beat: u32, tick: f64) -> PyResult<f64> {
+        if bar == 0 {
+            return Err(Error::value("bars are counted from 1").into());
+        }
+
+        let beats = self.meter.beats_in_measure(bar - 1) as u32;
+        if beat == 0 || beat > beats {
+            return Err(Error::value(format!("beat {} is outside of bar {} with {} beats", beat, bar, beats)).into());
+        }
+
+        let tick = self.meter.bbt_to_tick(midi::BarBeatTick { bar, beat, tick });
+        Ok(self.converter.beat_to_time(tick / self.converter.resolution() as f64))
+    }
+
+    /// Returns the index, counting from 0, of the bar containing the given time in seconds.
+    fn get_measure_index(&self, time: f64) -> u32 {
+        let tick = self.converter.time_to_beat(time) * self.converter.resolution() as f64;
+        self.meter.measure_index(tick)
+    }
+
+    /// Converts a tick into an exact number of seconds, given as a `fractions.Fraction`.
+    fn tick_to_exact(&self, py: Python<'_>, tick: u32) -> PyResult<PyObject> {
+        let time = self.converter.tick_to_exact(tick);
+        let fraction = py.import("fractions")?.getattr("Fraction")?;
+        Ok(fraction.call1((time.numer(), time.denom()))?.into())
+    }
+
+    /// Converts an exact number of seconds, such as a `fractions.Fraction`, into the nearest tick.
+    /// Times obtained from `tick_to_exact` are converted back into the original tick.
+    fn exact_to_tick(&self, time: &PyAny) -> PyResult<u32> {
+        let numer: u64 = time.getattr("numerator")?.extract()?;
+        let denom: u64 = time.getattr("denominator")?.extract()?;
+        if denom == 0 {
+            return Err(Error::value("the denominator of a time can not be zero").into());
         }
+        Ok(self.converter.exact_to_tick(midi::Rational::new(numer, denom)))
     }
 }
 
+fn check_slice_range(start: f64, end: f64) -> Result<(), Error> {
+    if start < 0.0 || end <= start {
+        return Err(Error::value(format!("invalid range to slice, {} to {}", start, end)));
+    }
+    Ok(())
+}
+
+/// Builds the bar lines of the time signatures, which are timed in seconds.
+fn meter_converter(time_signatures: &[TimeSignature], converter: &midi::TickConverter) -> midi::MeterConverter {
+    let time_signatures: Vec<_> = time_signatures
+        .iter()
+        .map(|ts| midi::TimeSignature::<midi::TickTime> {
+            numerator: ts.numerator,
+            denominator: ts.denominator,
+            time: converter.time_to_tick(ts.time),
+        })
+        .collect();
+    midi::MeterConverter::new(&time_signatures, converter.resolution())
+}
+
 impl MidiObject {
-    fn from_file(file_path: &str) -> PyResult<Self> {
+    fn from_file(py: Python<'_>, file_path: &str, options: &LoadOptions) -> PyResult<Self> {
         let mut file = File::open(file_path)?;
         let mut data = Vec::new();
         file.read_to_end(&mut data)?;
 
-        let mut smf = midly::Smf::parse(&data)
+        MidiObject::from_data(py, &data, options)
+    }
+
+    /// Copies the data of the instruments.
+    fn instrument_data(&self, py: Python<'_>) -> Vec<midi::Instrument<midi::RealTime>> {
+        self.instruments
+            .iter()
+            .map(|instrument| midi::Instrument::clone(&instrument.borrow(py).0))
+            .collect()
+    }
+
+    /// Collects the notes of the pitched instruments in their order,
+    /// along with the index of the instrument of each note and its index within it.
+    fn pitched_notes(&self, py: Python<'_>) -> (Vec<midi::Note<midi::RealTime>>, Vec<(usize, usize)>) {
+        let mut notes = vec![];
+        let mut indices = vec![];
+        for (i, instrument) in self.instruments.iter().enumerate() {
+            let instrument = instrument.borrow(py);
+            if !instrument.0.is_drum {
+                notes.extend(instrument.0.notes.iter().cloned());
+                indices.extend((0..instrument.0.notes.len()).map(|j| (i, j)));
+            }
+        }
+        (notes, indices)
+    }
+
+    /// Combines the events of both files, delaying those of `other` by `offset` seconds.
+    fn combine(
+        &self,
+        py: Python<'_>,
+        other: &MidiObject,
+        offset: f64,
+        converter: Arc<midi::TickConverter>,
+    ) -> PyResult<Self> {
+        let instruments = midi::merge_instruments(&self.instrument_data(py), &other.instrument_data(py), offset)
+            .into_iter()
+            .map(|instrument| Py::new(py, Instrument(Arc::new(instrument), converter.clone(), None)))
+            .collect::<PyResult<_>>()?;
+
+        let mut combined = MidiObject {
+            instruments,
+            key_signature_changes: self.key_signature_changes.clone(),
+            time_signature_changes: self.time_signature_changes.clone(),
+            lyrics: self.lyrics.clone(),
+            text_events: self.text_events.clone(),
+            dangling_notes: self.dangling_notes.clone(),
+            end_of_file: self.end_of_file,
+            meter: self.meter.clone(),
+            converter,
+        };
+        midi::merge_event_times(&mut combined.key_signature_changes, &other.key_signature_changes, |ks| &mut ks.time, offset);
+        midi::merge_event_times(&mut combined.time_signature_changes, &other.time_signature_changes, |ts| &mut ts.time, offset);
+        midi::merge_event_times(&mut combined.lyrics, &other.lyrics, |lyric| &mut lyric.time, offset);
+        midi::merge_event_times(&mut combined.text_events, &other.text_events, |text| &mut text.time, offset);
+        midi::merge_event_times(&mut combined.dangling_notes, &other.dangling_notes, |note| &mut note.start, offset);
+        combined.meter = meter_converter(&combined.time_signature_changes, &combined.converter);
+
+        Ok(combined)
+    }
+
+    fn from_data(py: Python<'_>, data: &[u8], options: &LoadOptions) -> PyResult<Self> {
+        let mut smf = midly::Smf::parse(data)
             .map_err(Error::from)?;
 
-        let mut reader = midi::MidiReader::new(&mut smf);
+        let mut reader = midi::MidiReader::new(&mut smf)?
+            .with_note_pairing(options.pairing)
+            .with_text_encoding(options.encoding);
+
+        let converter = Arc::new(reader.tick_converter().clone());
+        let instruments = reader.build_tick_instrument_data()
+            .into_iter()
+            .map(|ticks| {
+                let mut instrument = Instrument(
+                    Arc::new(ticks.clone().into_real_time(&converter)),
+                    converter.clone(),
+                    Some(Arc::new(ticks)),
+                );
+                if let Some(threshold) = options.sustain_threshold {
+                    instrument.data_mut().apply_sustain_pedal(threshold);
+                }
+                Py::new(py, instrument)
+            })
+            .collect::<PyResult<_>>()?;
 
-        let instruments = reader.build_instrument_data()
+        let dangling_notes = reader.dangling_notes()
             .into_iter()
-            .map(|instrument| Instrument(Arc::new(instrument)))
+            .map(|note| DanglingNote {
+                pitch: note.pitch,
+                velocity: note.velocity,
+                start: note.start_time,
+                track: note.track,
+                channel: note.channel,
+            })
             .collect();
-        
-        Ok(MidiObject {
-            resolution: 0,
+
+        let metadata = reader.build_tick_metadata();
+        let meter = midi::MeterConverter::new(&metadata.time_signature_changes, reader.resolution());
+
+        let mut midi = MidiObject {
             instruments,
-        })
+            key_signature_changes: vec![],
+            time_signature_changes: vec![],
+            lyrics: vec![],
+            text_events: vec![],
+            dangling_notes,
+            end_of_file: reader.end_time(),
+            converter,
+            meter,
+        };
+        midi.set_metadata(metadata.into_real_time(reader.tick_converter()));
+        Ok(midi)
+    }
+
+    /// Copies the key and time signatures, lyrics and text events.
+    fn metadata(&self) -> midi::Metadata<midi::RealTime> {
+        midi::Metadata {
+            key_signature_changes: self.key_signature_changes
+                .iter()
+                .map(|ks| midi::KeySignature { key_number: ks.key_number, time: ks.time })
+                .collect(),
+            time_signature_changes: self.time_signature_changes
+                .iter()
+                .map(|ts| midi::TimeSignature { numerator: ts.numerator, denominator: ts.denominator, time: ts.time })
+                .collect(),
+            lyrics: self.lyrics
+                .iter()
+                .map(|lyric| midi::Lyric { text: lyric.text.clone(), time: lyric.time })
+                .collect(),
+            text_events: self.text_events
+                .iter()
+                .map(|text| midi::Text { text: text.text.clone(), time: text.time })
+                .collect(),
+        }
+    }
+
+    /// Replaces the key and time signatures, lyrics and text events.
+    fn set_metadata(&mut self, metadata: midi::Metadata<midi::RealTime>) {
+        self.key_signature_changes = metadata
+            .key_signature_changes
+            .into_iter()
+            .map(|ks| KeySignature { key_number: ks.key_number, time: ks.time })
+            .collect();
+        self.time_signature_changes = metadata
+            .time_signature_changes
+            .into_iter()
+            .map(|ts| TimeSignature {
+                numerator: ts.numerator,
+                denominator: ts.denominator,
+                time: ts.time,
+            })
+            .collect();
+        self.lyrics = metadata
+            .lyrics
+            .into_iter()
+            .map(|lyric| Lyric { text: lyric.text, time: lyric.time })
+            .collect();
+        self.text_events = metadata
+            .text_events
+            .into_iter()
+            .map(|text| Text { text: text.text, time: text.time })
+            .collect();
     }
 }
\ No newline at end of file
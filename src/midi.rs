@@ -1,497 +1,1030 @@
-use arrayvec::ArrayVec;
-use midly::{MetaMessage, MidiMessage, TrackEvent, TrackEventKind};
-#[cfg(test)]
-use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, VecDeque};
-use std::mem::{self, take};
-
-#[cfg(test)]
-mod test;
-
-pub trait InspectMutExt: Sized {
-    type Inner;
-
-    fn inspect_mut(self, f: impl FnOnce(&mut Self::Inner)) -> Self;
-}
-
-impl<T> InspectMutExt for Option<T> {
-    type Inner = T;
-
-    fn inspect_mut(self, f: impl FnOnce(&mut Self::Inner)) -> Self {
-        self.map(|mut v| {
-            f(&mut v);
-            v
-        })
-    }
-}
-
-const DEFAULT_TEMPO: u16 = 50000;
-const DEFAULT_TICKS_PER_BEAT: u16 = 480;
-
-pub type ProgramNo = u8;
-pub type ChannelNo = u8;
-pub type ControlNo = u8;
-
-pub type ControlValue = u8;
-pub type Pitch = u8;
-pub type PitchBendValue = u16;
-pub type Velocity = u8;
-pub type MidiTime = u32;
+use crate::err::Error;
+use arrayvec::ArrayVec;
+use midly::{MetaMessage, MidiMessage, TrackEvent, TrackEventKind};
+#[cfg(test)]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::mem::{self, take};
+
+mod align;
+mod chords;
+mod clean;
+mod combine;
+mod evaluate;
+mod features;
+mod key;
+mod melody;
+mod meter;
+mod pedal;
+mod quantize;
+mod roll;
+mod slice;
+mod stats;
+mod transpose;
+mod voices;
+mod warp;
+#[cfg(test)]
+mod test;
+
+pub use align::{align, chroma_features, dynamic_time_warping, Alignment};
+pub use chords::{estimate_chords, match_chord, Chord, ChordQuality, ChordSegment};
+pub use combine::{merge_event_times, merge_instruments};
+pub use evaluate::{
+    active_frames, average_overlap_ratio, f_measure, frame_scores, match_note_offsets, match_note_onsets, match_notes,
+    match_notes_with_velocity, note_scores, FrameScores, NoteScores, NoteTolerances,
+};
+pub use features::{normalize_histogram, normalize_transitions, PitchClassHistogram, PitchClassTransitions};
+pub use key::{
+    estimate_key, estimate_key_between, estimate_key_curve, key_number_to_key_name, pitch_class_name, KeyEstimate,
+    KeyProfile,
+};
+pub use melody::{extract_melody, MelodyStrategy};
+pub use meter::{BarBeatTick, MeterConverter};
+pub use pedal::{PedalInterval, SOFT_PEDAL, SOSTENUTO_PEDAL, SUSTAIN_PEDAL};
+pub use quantize::{note_value_in_beats, Grid};
+pub use roll::piano_roll_to_instrument;
+pub use slice::slice_event_times;
+pub use stats::{program_to_instrument_class, InstrumentStats, Stats, VELOCITY_BIN_SIZE};
+pub use transpose::{key_name_to_key_number, PitchOverflow};
+pub use voices::separate_voices;
+pub use warp::{adjust_event_times, TimeWarp};
+
+/// The tempo of files without tempo events, 120 beats per minute in microseconds per beat.
+pub const DEFAULT_TEMPO: u32 = 500_000;
+/// The resolution of new files in ticks per beat, the same as pretty-midi.
+pub const DEFAULT_RESOLUTION: u16 = 220;
+const MICROSECONDS_PER_SECOND: u64 = 1_000_000;
+const PITCH_BEND_CENTER: i16 = 8192;
+const DRUM_CHANNEL: ChannelNo = 9;
+
+pub type ProgramNo = u8;
+pub type ChannelNo = u8;
+pub type ControlNo = u8;
+
+pub type ControlValue = u8;
+pub type Pitch = u8;
+pub type PitchBendValue = u16;
+pub type Velocity = u8;
+pub type MidiTime = u32;
 
 /// Abstracts over different units of time that can be used
-/// to represent the start and end times of a note.
+/// to represent the start and end times of a note.
 pub trait TimeUnit {
-    /// The actual underlying type that stores the time data.
-    type Repr: std::fmt::Debug + Clone;
-}
-
-/// Represents time as seconds.
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-pub struct RealTime;
-impl TimeUnit for RealTime {
-    type Repr = f32;
-}
-
-/// Represents time as MIDI ticks.
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-pub struct TickTime;
-impl TimeUnit for TickTime {
-    type Repr = MidiTime;
-}
-
-#[cfg_attr(test, derive(Serialize, Deserialize))]
-#[derive(PartialEq, Eq, Debug, Clone)]
-pub struct Note<T: TimeUnit> {
-    pub pitch: Pitch,
-    pub velocity: Velocity,
-
-    #[cfg_attr(test, serde(alias = "start"))]
-    pub start_time: T::Repr,
-    #[cfg_attr(test, serde(alias = "end"))]
-    pub end_time: T::Repr,
-}
-
-#[derive(Debug, Clone)]
-pub struct PitchBend {
-    bend: PitchBendValue,
-    time: MidiTime,
-}
-
-#[derive(Debug, Clone)]
-pub struct ControlChange {
-    number: ControlNo,
-    value: ControlValue,
-    time: MidiTime,
-}
-
-pub type TickScale = (u32, f32);
-
-fn as_tempo_change(event: &TrackEvent) -> Option<TickScale> {
-    match event.kind {
-        TrackEventKind::Meta(MetaMessage::Tempo(x)) => Some((event.delta.as_int(), x.as_int() as f32)),
-        _ => None,
-    }
-}
-
-fn generate_tick_scales(track: &midly::Track, resolution: u16) -> VecDeque<TickScale> {
-    let resolution = resolution as f32;
-    let mut last_tick_scale = -1.0;
-
-    let mut scales: VecDeque<_> = track
-        .into_iter()
-        .filter_map(as_tempo_change)
-        .filter_map(|(time, tempo)| {
-            let tick_scale = 60.0 / ((6e7 / tempo) * resolution);
-            if tick_scale != last_tick_scale {
-                last_tick_scale = tick_scale;
-                Some((time, tick_scale))
-            } else {
-                None
-            }
-        })
-        .collect();
-
-    let missing_initial_scale = scales.front().map(|(time, _)| *time > 0).unwrap_or(false);
-
-    if missing_initial_scale {
-        scales.push_front((0, 60.0 / (120.0 * resolution)));
-    }
-
-    scales
-}
-
-#[derive(Debug, Clone)]
-pub struct Instrument<T: TimeUnit> {
-    pub program: ProgramNo,
-    pub name: String,
-    pub notes: Vec<Note<T>>,
-    pub pitch_bends: Vec<PitchBend>,
-    pub control_changes: Vec<ControlChange>,
-}
-
-impl<T: TimeUnit> Instrument<T> {
-    fn new(program: ProgramNo) -> Self {
-        Instrument {
-            program,
-            name: String::new(),
-            notes: vec![],
-            pitch_bends: vec![],
-            control_changes: vec![],
-        }
-    }
-
-    fn is_drum(&self) -> bool {
-        self.program == 9
-    }
-}
-
-impl Instrument<TickTime> {
-    /// Converts an instrument with time meassured in ticks
-    /// into an instrument with time meassure into real time.
-    fn to_real_time(self, scales: &[TickScale]) -> Instrument<RealTime> {
-        let time_thresholds = scales
-            .iter()
-            .skip(1)
-            .map(|(time, _)| *time)
-            .chain(std::iter::once(u32::MAX));
-        let scales = scales
-            .iter()
-            .take(scales.len())
-            .map(|(_, scale)| *scale);
-
-        let mut scales = time_thresholds.zip(scales);
-        
-        let mut current_scale = scales
-            .next()
-            .expect("There has to be atleast one scale to convert from tick time to real time.");
-
-        let mut last_end = (0, 0f32);
-        let mut last = (0, 0f32);
-        let notes = self.notes.into_iter().map(|note| {
-            while note.start_time > current_scale.0 {
-                current_scale = scales.next().unwrap();
-                last_end = last;
-                
-                println!("update current");
-            }
-
-            let real_offset = last_end.1;
-            let new_ticks_start = (note.start_time - last_end.0) as f32;
-            // TODO idk if it is correct to convert end time here
-            let new_ticks_end = (note.end_time - last_end.0) as f32;
-
-            Note::<RealTime> {
-                start_time: new_ticks_start * current_scale.1 + real_offset,
-                end_time: new_ticks_end * current_scale.1 + real_offset,
-                ..note
-            }
-        }).collect();
-
-        Instrument { notes, ..self }
-    }
-}
-
-#[derive(Default)]
-struct ChannelState {
-    active_notes: ArrayVec<Vec<(MidiTime, Velocity)>, 128>,
-    current_program: ProgramNo,
-
-    straggler_notes: Option<Box<Instrument<TickTime>>>,
-    instruments: HashMap<ProgramNo, Box<Instrument<TickTime>>>,
-}
-
-impl ChannelState {
-    fn create_instrument(&mut self, program: ProgramNo) -> &mut Instrument<TickTime> {
-        self.straggler_notes
-            .take()
-            .or_else(|| Some(Box::new(Instrument::new(program))))
-            .and_then(|instrument| {
-                self.instruments.insert(program, instrument);
-                self.instruments.get_mut(&program)
-            })
-            .map(|v| v.as_mut())
-            .unwrap()
-    }
-
-    /// Gets the instrument or creates it from the straggler instrument.
+    /// The actual underlying type that stores the time data.
+    type Repr: std::fmt::Debug + Clone;
+}
+
+/// Represents time as seconds.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RealTime;
+impl TimeUnit for RealTime {
+    type Repr = f64;
+}
+
+/// Represents time as MIDI ticks.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TickTime;
+impl TimeUnit for TickTime {
+    type Repr = MidiTime;
+}
+
+/// Represents time as quarter notes since the start of the file,
+/// independent of both the tempo and the resolution of the file.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct BeatTime;
+impl TimeUnit for BeatTime {
+    type Repr = f64;
+}
+
+/// A non-negative fraction kept in lowest terms.
+/// Used to represent time as an exact number of seconds,
+/// which unlike a float converts back into the original ticks without loss.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Rational {
+    numer: u64,
+    denom: u64,
+}
+
+impl Rational {
+    pub fn new(numer: u64, denom: u64) -> Self {
+        assert!(denom != 0, "The denominator of a rational can not be zero.");
+
+        let divisor = gcd(numer, denom);
+        Rational {
+            numer: numer / divisor,
+            denom: denom / divisor,
+        }
+    }
+
+    pub fn numer(&self) -> u64 {
+        self.numer
+    }
+
+    pub fn denom(&self) -> u64 {
+        self.denom
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.numer as u128 * other.denom as u128).cmp(&(other.numer as u128 * self.denom as u128))
+    }
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.max(1)
+}
+
+#[cfg_attr(test, derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Note<T: TimeUnit> {
+    pub pitch: Pitch,
+    pub velocity: Velocity,
+
+    #[cfg_attr(test, serde(alias = "start"))]
+    pub start_time: T::Repr,
+    #[cfg_attr(test, serde(alias = "end"))]
+    pub end_time: T::Repr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PitchBend<T: TimeUnit> {
+    pub bend: PitchBendValue,
+    pub time: T::Repr,
+}
+
+impl<T: TimeUnit> PitchBend<T> {
+    /// The bend relative to the centre of the pitch wheel,
+    /// i.e. in the range `-8192..=8191` as used by pretty-midi.
+    pub fn pitch(&self) -> i16 {
+        self.bend as i16 - PITCH_BEND_CENTER
+    }
+}
+
+impl<T: TimeUnit> Note<T> {
+    fn map_time<U: TimeUnit>(self, f: impl Fn(T::Repr) -> U::Repr) -> Note<U> {
+        Note {
+            pitch: self.pitch,
+            velocity: self.velocity,
+            start_time: f(self.start_time),
+            end_time: f(self.end_time),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlChange<T: TimeUnit> {
+    pub number: ControlNo,
+    pub value: ControlValue,
+    pub time: T::Repr,
+}
+
+#[derive(Debug, Clone)]
+pub struct KeySignature<T: TimeUnit> {
+    /// Key number in the range `0..24`, where `0..12` are the major keys
+    /// starting from C and `12..24` are the minor keys starting from C.
+    pub key_number: u8,
+    pub time: T::Repr,
+}
+
+impl KeySignature<TickTime> {
+    fn from_meta(sharps: i8, minor: bool, time: MidiTime) -> Self {
+        let tonic = (sharps as i32 * 7).rem_euclid(12) as u8;
+        let key_number = if minor {
+            (tonic + 9) % 12 + 12
+        } else {
+            tonic
+        };
+
+        KeySignature { key_number, time }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TimeSignature<T: TimeUnit> {
+    pub numerator: u8,
+    pub denominator: u8,
+    pub time: T::Repr,
+}
+
+#[derive(Debug, Clone)]
+pub struct Lyric<T: TimeUnit> {
+    pub text: String,
+    pub time: T::Repr,
+}
+
+#[derive(Debug, Clone)]
+pub struct Text<T: TimeUnit> {
+    pub text: String,
+    pub time: T::Repr,
+}
+
+/// Meta events that apply to the file as a whole rather than to a single instrument.
+#[derive(Debug, Clone)]
+pub struct Metadata<T: TimeUnit> {
+    pub key_signature_changes: Vec<KeySignature<T>>,
+    pub time_signature_changes: Vec<TimeSignature<T>>,
+    pub lyrics: Vec<Lyric<T>>,
+    pub text_events: Vec<Text<T>>,
+}
+
+impl Metadata<TickTime> {
+    /// Collects the metadata of a file whose tracks use absolute time.
+    /// Like pretty-midi, key and time signatures are only read from the first track
+    /// while lyrics and text events are gathered from all tracks.
+    fn from_tracks(tracks: &[midly::Track], encoding: TextEncoding) -> Self {
+        let mut metadata = Metadata {
+            key_signature_changes: vec![],
+            time_signature_changes: vec![],
+            lyrics: vec![],
+            text_events: vec![],
+        };
+
+        for event in tracks.iter().take(1).flatten() {
+            let time = event.delta.as_int();
+            match event.kind {
+                TrackEventKind::Meta(MetaMessage::KeySignature(sharps, minor)) => metadata
+                    .key_signature_changes
+                    .push(KeySignature::from_meta(sharps, minor, time)),
+                TrackEventKind::Meta(MetaMessage::TimeSignature(numerator, denominator, ..)) => {
+                    // Denominators past 2^7 do not fit and bars without beats never end, such events are skipped
+                    match 1u8.checked_shl(denominator as u32) {
+                        Some(denominator) if numerator > 0 => metadata.time_signature_changes.push(TimeSignature {
+                            numerator,
+                            denominator,
+                            time,
+                        }),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for event in tracks.iter().flatten() {
+            let time = event.delta.as_int();
+            match event.kind {
+                TrackEventKind::Meta(MetaMessage::Lyric(text)) => metadata.lyrics.push(Lyric {
+                    text: encoding.decode(text),
+                    time,
+                }),
+                TrackEventKind::Meta(MetaMessage::Text(text)) => metadata.text_events.push(Text {
+                    text: encoding.decode(text),
+                    time,
+                }),
+                _ => {}
+            }
+        }
+
+        // Each track is already ordered, so a stable sort merges them
+        // the same way pretty-midi does.
+        metadata.lyrics.sort_by_key(|lyric| lyric.time);
+        metadata.text_events.sort_by_key(|text| text.time);
+
+        metadata
+    }
+
+    pub fn into_real_time(self, converter: &TickConverter) -> Metadata<RealTime> {
+        self.map_time(|tick| converter.tick_to_time(tick))
+    }
+}
+
+impl<T: TimeUnit> Metadata<T> {
+    /// Converts the time of every event with the given function.
+    pub fn map_time<U: TimeUnit>(self, f: impl Fn(T::Repr) -> U::Repr) -> Metadata<U> {
+        Metadata {
+            key_signature_changes: self
+                .key_signature_changes
+                .into_iter()
+                .map(|ks| KeySignature { key_number: ks.key_number, time: f(ks.time) })
+                .collect(),
+            time_signature_changes: self
+                .time_signature_changes
+                .into_iter()
+                .map(|ts| TimeSignature {
+                    numerator: ts.numerator,
+                    denominator: ts.denominator,
+                    time: f(ts.time),
+                })
+                .collect(),
+            lyrics: self
+                .lyrics
+                .into_iter()
+                .map(|lyric| Lyric { text: lyric.text, time: f(lyric.time) })
+                .collect(),
+            text_events: self
+                .text_events
+                .into_iter()
+                .map(|text| Text { text: text.text, time: f(text.time) })
+                .collect(),
+        }
+    }
+}
+
+pub type TickScale = (u32, f64);
+
+/// A tempo change given as `(tick, microseconds per beat)`.
+pub type TempoChange = (MidiTime, u32);
+
+fn as_tempo_change(event: &TrackEvent) -> Option<TempoChange> {
+    match event.kind {
+        TrackEventKind::Meta(MetaMessage::Tempo(x)) => Some((event.delta.as_int(), x.as_int())),
+        _ => None,
+    }
+}
+
+fn tick_scale(tempo: u32, resolution: u16) -> f64 {
+    60.0 / ((6e7 / tempo as f64) * resolution as f64)
+}
+
+/// Generates the tempo map from the tempo events of all tracks,
+/// which are expected to use absolute time.
+fn generate_tempo_map(tracks: &[midly::Track]) -> Vec<TempoChange> {
+    // Tempo events of later tracks follow those of earlier tracks on the same tick.
+    let mut tempo_changes: Vec<_> = tracks
+        .iter()
+        .flat_map(|track| track.iter().filter_map(as_tempo_change))
+        .collect();
+    tempo_changes.sort_by_key(|(time, _)| *time);
+
+    let mut tempo_map = vec![(0, DEFAULT_TEMPO)];
+
+    for (time, tempo) in tempo_changes {
+        if time == 0 {
+            // A tempo event at the very start replaces the default tempo
+            tempo_map = vec![(0, tempo)];
+        } else if tempo_map.last().map(|(_, last)| *last != tempo).unwrap_or(true) {
+            tempo_map.push((time, tempo));
+        }
+    }
+
+    tempo_map
+}
+
+/// Converts between ticks and seconds according to a tempo map.
+#[derive(Debug, Clone)]
+pub struct TickConverter {
+    resolution: u16,
+    tempo_map: Vec<TempoChange>,
+    scales: Vec<TickScale>,
+    /// The time in seconds at which each of the scales starts.
+    offsets: Vec<f64>,
+    /// The exact time at which each of the scales starts,
+    /// meassured in microseconds per beat times ticks.
+    exact_offsets: Vec<u64>,
+}
+
+impl TickConverter {
+    pub fn new(tempo_map: &[TempoChange], resolution: u16) -> Self {
+        assert!(
+            tempo_map.first().map(|(time, _)| *time == 0).unwrap_or(false),
+            "The first tempo change has to start at tick 0."
+        );
+
+        let scales: Vec<_> = tempo_map
+            .iter()
+            .map(|(time, tempo)| (*time, tick_scale(*tempo, resolution)))
+            .collect();
+
+        let mut offsets = Vec::with_capacity(scales.len());
+        let mut exact_offsets = Vec::with_capacity(scales.len());
+        let (mut offset, mut exact_offset) = (0.0, 0);
+        let mut last = (tempo_map[0], scales[0].1);
+        for ((time, tempo), (_, scale)) in tempo_map.iter().zip(&scales) {
+            let ((last_time, last_tempo), last_scale) = last;
+            offset += (time - last_time) as f64 * last_scale;
+            exact_offset += (time - last_time) as u64 * last_tempo as u64;
+            offsets.push(offset);
+            exact_offsets.push(exact_offset);
+            last = ((*time, *tempo), *scale);
+        }
+
+        TickConverter {
+            resolution,
+            tempo_map: tempo_map.to_vec(),
+            scales,
+            offsets,
+            exact_offsets,
+        }
+    }
+
+    pub fn tick_to_time(&self, tick: MidiTime) -> f64 {
+        let i = self.scales.partition_point(|(start, _)| *start <= tick) - 1;
+        let (start, scale) = self.scales[i];
+        self.offsets[i] + (tick - start) as f64 * scale
+    }
+
+    /// Converts seconds into the nearest tick.
+    pub fn time_to_tick(&self, time: f64) -> MidiTime {
+        let i = self.offsets.partition_point(|offset| *offset <= time).max(1) - 1;
+        let (start, scale) = self.scales[i];
+        start + ((time - self.offsets[i]) / scale).round().max(0.0) as MidiTime
+    }
+
+    pub fn tick_to_beat(&self, tick: MidiTime) -> f64 {
+        tick as f64 / self.resolution as f64
+    }
+
+    /// Converts a position in beats into seconds.
+    /// Unlike [`TickConverter::tick_to_time`] this accepts fractional ticks.
+    pub fn beat_to_time(&self, beat: f64) -> f64 {
+        let tick = beat * self.resolution as f64;
+        let i = self.scales.partition_point(|(start, _)| *start as f64 <= tick).max(1) - 1;
+        let (start, scale) = self.scales[i];
+        self.offsets[i] + (tick - start as f64) * scale
+    }
+
+    /// Converts seconds into a, possibly fractional, position in beats.
+    pub fn time_to_beat(&self, time: f64) -> f64 {
+        let i = self.offsets.partition_point(|offset| *offset <= time).max(1) - 1;
+        let (start, scale) = self.scales[i];
+        (start as f64 + (time - self.offsets[i]) / scale) / self.resolution as f64
+    }
+
+    pub fn tick_to_exact(&self, tick: MidiTime) -> Rational {
+        let i = self.tempo_map.partition_point(|(start, _)| *start <= tick) - 1;
+        let (start, tempo) = self.tempo_map[i];
+        let numer = self.exact_offsets[i] + (tick - start) as u64 * tempo as u64;
+        Rational::new(numer, MICROSECONDS_PER_SECOND * self.resolution as u64)
+    }
+
+    /// Converts an exact time into the nearest tick.
+    /// This is the inverse of [`TickConverter::tick_to_exact`].
+    pub fn exact_to_tick(&self, time: Rational) -> MidiTime {
+        // The time in the same unit as the exact offsets, scaled up by the denominator of the time.
+        let scaled = time.numer as u128 * MICROSECONDS_PER_SECOND as u128 * self.resolution as u128;
+        let denom = time.denom as u128;
+
+        let i = self.exact_offsets
+            .partition_point(|offset| *offset as u128 * denom <= scaled)
+            .max(1) - 1;
+        let (start, tempo) = self.tempo_map[i];
+        let remainder = scaled.saturating_sub(self.exact_offsets[i] as u128 * denom);
+        let step = tempo as u128 * denom;
+        start + ((remainder + step / 2) / step) as MidiTime
+    }
+
+    pub fn resolution(&self) -> u16 {
+        self.resolution
+    }
+
+    pub fn tick_scales(&self) -> &[TickScale] {
+        &self.scales
+    }
+
+    /// Returns the tempo changes as `(time in seconds, tempo in beats per minute)` pairs.
+    pub fn tempo_changes(&self) -> Vec<(f64, f64)> {
+        self.tempo_map
+            .iter()
+            .zip(&self.offsets)
+            .map(|((_, tempo), time)| (*time, 6e7 / *tempo as f64))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Instrument<T: TimeUnit> {
+    pub program: ProgramNo,
+    pub is_drum: bool,
+    pub name: String,
+    pub notes: Vec<Note<T>>,
+    pub pitch_bends: Vec<PitchBend<T>>,
+    pub control_changes: Vec<ControlChange<T>>,
+}
+
+impl<T: TimeUnit> Instrument<T> {
+    fn new(program: ProgramNo, is_drum: bool) -> Self {
+        Instrument {
+            program,
+            is_drum,
+            name: String::new(),
+            notes: vec![],
+            pitch_bends: vec![],
+            control_changes: vec![],
+        }
+    }
+}
+
+impl<T: TimeUnit> Instrument<T> {
+    /// Converts the time of every event of the instrument with the given function.
+    pub fn map_time<U: TimeUnit>(self, f: impl Fn(T::Repr) -> U::Repr) -> Instrument<U> {
+        Instrument {
+            program: self.program,
+            is_drum: self.is_drum,
+            name: self.name,
+            notes: self.notes.into_iter().map(|note| note.map_time::<U>(&f)).collect(),
+            pitch_bends: self
+                .pitch_bends
+                .into_iter()
+                .map(|bend| PitchBend { bend: bend.bend, time: f(bend.time) })
+                .collect(),
+            control_changes: self
+                .control_changes
+                .into_iter()
+                .map(|cc| ControlChange { number: cc.number, value: cc.value, time: f(cc.time) })
+                .collect(),
+        }
+    }
+}
+
+impl Instrument<TickTime> {
+    /// Converts an instrument with time meassured in ticks
+    /// into an instrument with time meassure into real time.
+    pub fn into_real_time(self, converter: &TickConverter) -> Instrument<RealTime> {
+        self.map_time(|tick| converter.tick_to_time(tick))
+    }
+
+    pub fn into_beat_time(self, converter: &TickConverter) -> Instrument<BeatTime> {
+        self.map_time(|tick| converter.tick_to_beat(tick))
+    }
+}
+
+impl Instrument<RealTime> {
+    /// Converts an instrument with time meassured in seconds back into ticks.
+    pub fn into_tick_time(self, converter: &TickConverter) -> Instrument<TickTime> {
+        self.map_time(|time| converter.time_to_tick(time))
+    }
+
+    pub fn into_beat_time(self, converter: &TickConverter) -> Instrument<BeatTime> {
+        self.map_time(|time| converter.time_to_beat(time))
+    }
+}
+
+impl Instrument<BeatTime> {
+    pub fn into_real_time(self, converter: &TickConverter) -> Instrument<RealTime> {
+        self.map_time(|beat| converter.beat_to_time(beat))
+    }
+}
+
+/// Determines which pending note-on events a note-off event closes
+/// when several notes of the same pitch are active on a channel.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum NotePairing {
+    /// Closes every pending note that did not start on the same tick as the note-off.
+    /// Notes that did start on that tick are kept if any other note was closed,
+    /// otherwise they are discarded. This is what pretty-midi does.
+    #[default]
+    PrettyMidi,
+    /// Closes the pending note that was started first.
+    Fifo,
+    /// Closes the pending note that was started last.
+    Lifo,
+    /// Closes every pending note, including ones started on the same tick.
+    CloseAll,
+}
+
+/// Determines how the text of meta events is decoded.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    /// Decodes the text as UTF-8, replacing invalid sequences.
+    #[default]
+    Utf8,
+    /// Maps every byte to the character with the same code point.
+    /// This is what pretty-midi does by default and keeps the original bytes recoverable.
+    Latin1,
+}
+
+impl TextEncoding {
+    fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            TextEncoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            TextEncoding::Latin1 => bytes.iter().map(|&byte| byte as char).collect(),
+        }
+    }
+}
+
+/// A note-on event that was never closed by a note-off event before the end of its track.
+#[derive(Debug, Clone)]
+pub struct DanglingNote<T: TimeUnit> {
+    pub track: usize,
+    pub channel: ChannelNo,
+    pub pitch: Pitch,
+    pub velocity: Velocity,
+    pub start_time: T::Repr,
+}
+
+#[derive(Default)]
+struct ChannelState {
+    channel: ChannelNo,
+    active_notes: ArrayVec<Vec<(MidiTime, Velocity)>, 128>,
+    current_program: ProgramNo,
+
+    straggler_notes: Option<Box<Instrument<TickTime>>>,
+    instruments: HashMap<ProgramNo, Box<Instrument<TickTime>>>,
+}
+
+impl ChannelState {
+    fn create_instrument(&mut self, program: ProgramNo) -> &mut Instrument<TickTime> {
+        self.straggler_notes
+            .take()
+            .map(|mut instrument| {
+                instrument.program = program;
+                instrument
+            })
+            .or_else(|| Some(Box::new(Instrument::new(program, self.channel == DRUM_CHANNEL))))
+            .and_then(|instrument| {
+                self.instruments.insert(program, instrument);
+                self.instruments.get_mut(&program)
+            })
+            .map(|v| v.as_mut())
+            .unwrap()
+    }
+
+    /// Gets the instrument or creates it from the straggler instrument.
     fn get_or_create_instrument_mut(&mut self, program: ProgramNo) -> &mut Instrument<TickTime> {
-        // TODO maybe look into ways of minimizing the number of lookups required (we do quite a lot)
-        if self.instruments.contains_key(&program) {
-            self.instruments.get_mut(&program).unwrap()
-        } else {
-            self.create_instrument(program)
-        }
-    }
-
-    /// Gets the currently active instrument. Instrument selection priority:
-    /// `straggler > instruments[current_program] > new straggler`
-    fn current_instrument_mut(&mut self) -> &mut Instrument<TickTime> {
-        if let Some(ref mut inst) = self.straggler_notes {
-            inst
-        } else {
-            self.instruments
-                .get_mut(&self.current_program)
-                .or_else(|| {
-                    // We create an instrument for storing straggler notes
-                    // if one does exists and an instrument for the current program
-                    // also does not exist.
-                    self.straggler_notes = Some(Box::new(Instrument::new(0)));
-                    self.straggler_notes.as_mut()
-                })
-                .unwrap()
-        }
-    }
-
-    fn note_on(&mut self, time: MidiTime, key: u8, vel: u8) {
-        self.active_notes[key as usize].push((time, vel));
-    }
-
-    fn note_off(&mut self, time: MidiTime, key: u8) {
-        if self.active_notes[key as usize].len() > 0 {
-            // We move the note list out of the instrument
-            // to avoid mutable double borrowing
-            let instrument = &mut self.get_or_create_instrument_mut(self.current_program);
-            let mut notes = mem::take(&mut instrument.notes);
-
-            self.active_notes[key as usize]
-                .drain_filter(|(start, _)| *start != time)
-                .map(|(start, velocity)| Note {
-                    pitch: key,
-                    start_time: start,
-                    end_time: time,
-                    velocity,
-                })
-                .collect_into(&mut notes);
-
-            self.current_instrument_mut().notes = notes;
-        }
-    }
-
-    fn pitch_bend(&mut self, bend: PitchBendValue, time: MidiTime) {
-        let instrument = self.current_instrument_mut();
-        instrument.pitch_bends.push(PitchBend { bend, time });
-    }
-
-    fn control_change(&mut self, number: ControlNo, value: ControlValue, time: MidiTime) {
-        let instrument = self.current_instrument_mut();
-        instrument.control_changes.push(ControlChange {
-            number,
-            value,
-            time,
-        })
-    }
-}
-
-#[derive(Copy, Clone, Eq, PartialEq, Hash)]
-struct InstrumentID {
-    channel: u8,
-    program: u8,
-}
-
-impl InstrumentID {
-    fn new(channel: u8, program: u8) -> Self {
-        InstrumentID { channel, program }
-    }
-}
-
-#[derive(Default)]
-struct TrackState {
-    name: Option<String>,
-    channels: [ChannelState; 16],
-    instruments: HashMap<InstrumentID, Instrument<TickTime>>,
-}
-
-impl TrackState {
-    fn init_channels(&mut self) {
-        for channel in &mut self.channels {
-            while channel.active_notes.remaining_capacity() > 0 {
-                channel.active_notes.push(vec![])
-            }
-        }
-    }
-    
-    fn apply_event(&mut self, event: &TrackEvent) {
-        let time = event.delta.as_int();
-        match event.kind {
-            TrackEventKind::Midi {
-                channel,
-                ref message,
-            } => self.apply_midi_msg(channel.as_int(), message, time),
-
-            TrackEventKind::Meta(ref msg) => self.apply_meta_msg(msg),
-
-            TrackEventKind::SysEx(_) | TrackEventKind::Escape(_) => {}
-        }
-    }
-
-    fn apply_midi_msg(&mut self, channel: ChannelNo, msg: &MidiMessage, time: MidiTime) {
-        match msg {
-            | MidiMessage::ProgramChange { program } => {
-                self.channels[channel as usize].current_program = program.as_int()
-            }
-
-            | MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => self
-                .get_channel_mut(channel)
-                .note_on(time, key.as_int(), vel.as_int()),
-
-            | MidiMessage::NoteOff { key, .. } | MidiMessage::NoteOn { key, .. } => {
-                self.get_channel_mut(channel).note_off(time, key.as_int())
-            }
-
-            | MidiMessage::PitchBend {
-                bend: midly::PitchBend(bend),
-            } => self
-                .get_channel_mut(channel)
-                .pitch_bend(bend.as_int(), time),
-
-            | MidiMessage::Controller { controller, value } => self
-                .get_channel_mut(channel)
-                .control_change(controller.as_int(), value.as_int(), time),
-
-            // pretty-midi ignores these, so we do the same
-            | MidiMessage::Aftertouch { .. }
-            | MidiMessage::ChannelAftertouch { .. } => (),
-        }
-    }
-
-    fn apply_meta_msg(&mut self, msg: &MetaMessage) {
-        match msg {
-            | MetaMessage::TrackName(name) => {
-                self.name = Some(String::from_utf8_lossy(name).into_owned());
-            }
-
-            | MetaMessage::InstrumentName(_)
-            | MetaMessage::TrackNumber(..)
-            | MetaMessage::Text(..)
-            | MetaMessage::Copyright(..)
-            | MetaMessage::Lyric(..)
-            | MetaMessage::Marker(..)
-            | MetaMessage::CuePoint(..)
-            | MetaMessage::ProgramName(..)
-            | MetaMessage::DeviceName(..)
-            | MetaMessage::MidiChannel(..)
-            | MetaMessage::MidiPort(..)
-            | MetaMessage::EndOfTrack
-            | MetaMessage::Tempo(..)
-            | MetaMessage::SmpteOffset(..)
-            | MetaMessage::TimeSignature(..)
-            | MetaMessage::KeySignature(..)
-            | MetaMessage::SequencerSpecific(..)
-            | MetaMessage::Unknown(..) => {}
-        }
-    }
-
-    fn get_channel_mut(&mut self, channel: ChannelNo) -> &mut ChannelState {
-        &mut self.channels[channel as usize]
-    }
-}
-
-fn read_midi_file(path: &str) {}
-
-fn get_timing(smf: &midly::Smf) -> u16 {
-    match smf.header.timing {
-        midly::Timing::Metrical(t) => t.as_int(),
-        _ => panic!("Non metrical timing not supported."),
-    }
-}
-
-fn make_track_time_absolute(track: midly::Track) -> midly::Track {
-    let mut time = 0;
-    track
-        .into_iter()
-        .map(|event| {
-            time += event.delta.as_int();
-            TrackEvent {
-                delta: time.into(),
-                ..event
-            }
-        })
-        .collect()
-}
-
-fn get_max_tick<'l>(tracks: impl Iterator<Item = &'l midly::Track<'l>>) -> u32 {
-    const MAX_TICK: u32 = 10_000_000;
-    /*
-       The original code finds max by iterating over all events
-       max([max([ e.time for e in t] )
-                           for t in midi_data.tracks]) + 1
-       but since we converted all tracks to absolute time we can
-       simply take the last event of each track
-    */
-
-    1 + tracks
-        .map(|t| {
-            t.last()
-                .expect("all MIDI tracks should be non empty")
-                .delta
-                .as_int()
-        })
-        .max()
-        .unwrap()
-
-    /*
-    1 + tracks.flatten()
-        .map(|event| event.delta.as_int())
-        .max().unwrap_or(0)
-     */
-}
-
-pub struct MidiReader<'l> {
-    smf: &'l midly::Smf<'l>,
-    track_state: Vec<TrackState>,
-    track_offset: usize,
-}
-
-impl<'l> MidiReader<'l> {
-    pub fn new(src: &'l mut midly::Smf<'l>) -> Self {
-        let midly::Format::Parallel = src.header.format else {
-            panic!("SMF formats other than parallel (format 1) are not currently supported1");
-        };
+        // TODO maybe look into ways of minimizing the number of lookups required (we do quite a lot)
+        if self.instruments.contains_key(&program) {
+            self.instruments.get_mut(&program).unwrap()
+        } else {
+            self.create_instrument(program)
+        }
+    }
+
+    /// Gets the currently active instrument. Instrument selection priority:
+    /// `straggler > instruments[current_program] > new straggler`
+    fn current_instrument_mut(&mut self) -> &mut Instrument<TickTime> {
+        if let Some(ref mut inst) = self.straggler_notes {
+            inst
+        } else {
+            self.instruments
+                .get_mut(&self.current_program)
+                .or_else(|| {
+                    // We create an instrument for storing straggler notes
+                    // if one does exists and an instrument for the current program
+                    // also does not exist.
+                    self.straggler_notes = Some(Box::new(Instrument::new(0, self.channel == DRUM_CHANNEL)));
+                    self.straggler_notes.as_mut()
+                })
+                .unwrap()
+        }
+    }
+
+    fn note_on(&mut self, time: MidiTime, key: u8, vel: u8) {
+        self.active_notes[key as usize].push((time, vel));
+    }
+
+    /// Closes the active notes of the given key as chosen by the pairing policy.
+    /// Returns `true` if this created a new instrument for the current program.
+    fn note_off(&mut self, time: MidiTime, key: u8, pairing: NotePairing) -> bool {
+        let pending = &mut self.active_notes[key as usize];
+        let closed: Vec<_> = match pairing {
+            NotePairing::PrettyMidi => {
+                let closed: Vec<_> = pending
+                    .extract_if(.., |(start, _)| *start != time)
+                    .collect();
+                if closed.is_empty() {
+                    pending.clear();
+                }
+                closed
+            }
+            NotePairing::Fifo if !pending.is_empty() => vec![pending.remove(0)],
+            NotePairing::Fifo => vec![],
+            NotePairing::Lifo => pending.pop().into_iter().collect(),
+            NotePairing::CloseAll => mem::take(pending),
+        };
+
+        let notes: Vec<_> = closed
+            .into_iter()
+            .map(|(start, velocity)| Note {
+                pitch: key,
+                start_time: start,
+                end_time: time,
+                velocity,
+            })
+            .collect();
+
+        if notes.is_empty() {
+            return false;
+        }
+
+        let created = !self.instruments.contains_key(&self.current_program);
+        self.get_or_create_instrument_mut(self.current_program)
+            .notes
+            .extend(notes);
+        created
+    }
+
+    fn pitch_bend(&mut self, bend: PitchBendValue, time: MidiTime) {
+        let instrument = self.current_instrument_mut();
+        instrument.pitch_bends.push(PitchBend { bend, time });
+    }
+
+    fn control_change(&mut self, number: ControlNo, value: ControlValue, time: MidiTime) {
+        let instrument = self.current_instrument_mut();
+        instrument.control_changes.push(ControlChange {
+            number,
+            value,
+            time,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+struct InstrumentID {
+    channel: u8,
+    program: u8,
+}
+
+impl InstrumentID {
+    fn new(channel: u8, program: u8) -> Self {
+        InstrumentID { channel, program }
+    }
+}
+
+#[derive(Default)]
+struct TrackState {
+    name: Option<String>,
+    channels: [ChannelState; 16],
+    pairing: NotePairing,
+    encoding: TextEncoding,
+    /// The instruments of the track in the order they were created,
+    /// which is the order pretty-midi reports them in.
+    instrument_order: Vec<InstrumentID>,
+}
+
+impl TrackState {
+    fn init_channels(&mut self) {
+        for (i, channel) in self.channels.iter_mut().enumerate() {
+            channel.channel = i as ChannelNo;
+            while channel.active_notes.remaining_capacity() > 0 {
+                channel.active_notes.push(vec![])
+            }
+        }
+    }
+    
+    fn apply_event(&mut self, event: &TrackEvent) {
+        let time = event.delta.as_int();
+        match event.kind {
+            TrackEventKind::Midi {
+                channel,
+                ref message,
+            } => self.apply_midi_msg(channel.as_int(), message, time),
+
+            TrackEventKind::Meta(ref msg) => self.apply_meta_msg(msg),
+
+            TrackEventKind::SysEx(_) | TrackEventKind::Escape(_) => {}
+        }
+    }
+
+    fn apply_midi_msg(&mut self, channel: ChannelNo, msg: &MidiMessage, time: MidiTime) {
+        match msg {
+            | MidiMessage::ProgramChange { program } => {
+                self.channels[channel as usize].current_program = program.as_int()
+            }
+
+            | MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => self
+                .get_channel_mut(channel)
+                .note_on(time, key.as_int(), vel.as_int()),
+
+            | MidiMessage::NoteOff { key, .. } | MidiMessage::NoteOn { key, .. } => {
+                let pairing = self.pairing;
+                let state = self.get_channel_mut(channel);
+                if state.note_off(time, key.as_int(), pairing) {
+                    let program = state.current_program;
+                    self.instrument_order.push(InstrumentID::new(channel, program));
+                }
+            }
+
+            | MidiMessage::PitchBend {
+                bend: midly::PitchBend(bend),
+            } => self
+                .get_channel_mut(channel)
+                .pitch_bend(bend.as_int(), time),
+
+            | MidiMessage::Controller { controller, value } => self
+                .get_channel_mut(channel)
+                .control_change(controller.as_int(), value.as_int(), time),
+
+            // pretty-midi ignores these, so we do the same
+            | MidiMessage::Aftertouch { .. }
+            | MidiMessage::ChannelAftertouch { .. } => (),
+        }
+    }
+
+    fn apply_meta_msg(&mut self, msg: &MetaMessage) {
+        match msg {
+            | MetaMessage::TrackName(name) => {
+                self.name = Some(self.encoding.decode(name));
+            }
+
+            | MetaMessage::InstrumentName(_)
+            | MetaMessage::TrackNumber(..)
+            | MetaMessage::Text(..)
+            | MetaMessage::Copyright(..)
+            | MetaMessage::Lyric(..)
+            | MetaMessage::Marker(..)
+            | MetaMessage::CuePoint(..)
+            | MetaMessage::ProgramName(..)
+            | MetaMessage::DeviceName(..)
+            | MetaMessage::MidiChannel(..)
+            | MetaMessage::MidiPort(..)
+            | MetaMessage::EndOfTrack
+            | MetaMessage::Tempo(..)
+            | MetaMessage::SmpteOffset(..)
+            | MetaMessage::TimeSignature(..)
+            | MetaMessage::KeySignature(..)
+            | MetaMessage::SequencerSpecific(..)
+            | MetaMessage::Unknown(..) => {}
+        }
+    }
+
+    fn get_channel_mut(&mut self, channel: ChannelNo) -> &mut ChannelState {
+        &mut self.channels[channel as usize]
+    }
+}
+
+fn get_timing(smf: &midly::Smf) -> Result<u16, Error> {
+    match smf.header.timing {
+        midly::Timing::Metrical(t) => Ok(t.as_int()),
+        midly::Timing::Timecode(..) => Err(Error::unsupported("SMPTE timing is not supported")),
+    }
+}
+
+fn make_track_time_absolute(track: midly::Track) -> midly::Track {
+    let mut time = 0;
+    track
+        .into_iter()
+        .map(|event| {
+            time += event.delta.as_int();
+            TrackEvent {
+                delta: time.into(),
+                ..event
+            }
+        })
+        .collect()
+}
+
+fn get_max_tick<'l>(tracks: impl Iterator<Item = &'l midly::Track<'l>>) -> u32 {
+    /*
+       The original code finds max by iterating over all events
+       max([max([ e.time for e in t] )
+                           for t in midi_data.tracks]) + 1
+       but since we converted all tracks to absolute time we can
+       simply take the last event of each track
+    */
+    1 + tracks
+        .filter_map(|t| t.last())
+        .map(|event| event.delta.as_int())
+        .max()
+        .unwrap_or(0)
+}
+
+pub struct MidiReader<'l> {
+    smf: &'l midly::Smf<'l>,
+    resolution: u16,
+    converter: TickConverter,
+    track_state: Vec<TrackState>,
+    pairing: NotePairing,
+    encoding: TextEncoding,
+}
+
+impl<'l> MidiReader<'l> {
+    /// Prepares a file for reading. Single track files (format 0) are read like pretty-midi does,
+    /// by giving every channel of the track its own instruments.
+    /// Sequential files (format 2) and SMPTE timing are not supported.
+    pub fn new(src: &'l mut midly::Smf<'l>) -> Result<Self, Error> {
+        if let midly::Format::Sequential = src.header.format {
+            return Err(Error::unsupported("sequential SMF files (format 2) are not supported"));
+        }
+        let resolution = get_timing(src)?;
 
         let tracks = take(&mut src.tracks);
         src.tracks = tracks.into_iter().map(make_track_time_absolute).collect();
-        
-        let track_count = src.tracks.len();
-        MidiReader {
-            smf: src,
-            track_state: Vec::with_capacity(track_count - 1),
-            track_offset: 1,
-        }
-    }
-
-    fn build_track_state(&mut self) {
-        self.smf
-            .tracks
-            .iter()
-            .skip(self.track_offset)
+        
+        let track_count = src.tracks.len();
+        let converter = TickConverter::new(&generate_tempo_map(&src.tracks), resolution);
+        Ok(MidiReader {
+            smf: src,
+            resolution,
+            converter,
+            track_state: Vec::with_capacity(track_count),
+            pairing: NotePairing::default(),
+            encoding: TextEncoding::default(),
+        })
+    }
+
+    /// Sets the policy used to pair note-off events with pending note-on events.
+    pub fn with_note_pairing(mut self, pairing: NotePairing) -> Self {
+        self.pairing = pairing;
+        self
+    }
+
+    /// Sets how the text of track names, lyrics and text events is decoded.
+    pub fn with_text_encoding(mut self, encoding: TextEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    fn build_track_state(&mut self) {
+        self.smf
+            .tracks
+            .iter()
             .map(|track| {
-                let mut track_state = TrackState::default();
-                track_state.init_channels();
-                for event in track {
-                    track_state.apply_event(event);
-                }
-                track_state
-            })
-            .collect_into(&mut self.track_state);
-    }
-
-    pub fn build_instrument_data(&mut self) -> Vec<Instrument<RealTime>> {
-        if self.track_state.len() == 0 {
-            self.build_track_state();
-        }
-
-        let scales: Vec<_> = generate_tick_scales(&self.smf.tracks[0], DEFAULT_TICKS_PER_BEAT).into();
-        
-        self.track_state
-            .iter_mut()
-            .flat_map(|state| {
-                state.channels
-                    .iter_mut()
-                    .flat_map(|channel| channel.instruments
-                        .drain()
-                        .map(|(_, v)| v.to_real_time(scales.as_ref()))
-                        )
-            })
-            .collect()
-    }
-}
+                let mut track_state = TrackState {
+                    pairing: self.pairing,
+                    encoding: self.encoding,
+                    ..TrackState::default()
+                };
+                track_state.init_channels();
+                for event in track {
+                    track_state.apply_event(event);
+                }
+                track_state
+            })
+            .collect_into(&mut self.track_state);
+    }
+
+    pub fn build_instrument_data(&mut self) -> Vec<Instrument<RealTime>> {
+        self.build_tick_instrument_data()
+            .into_iter()
+            .map(|instrument| instrument.into_real_time(&self.converter))
+            .collect()
+    }
+
+    /// Builds the instruments of the file with time meassured in ticks.
+    /// Like [`MidiReader::build_instrument_data`] this can only be done once.
+    pub fn build_tick_instrument_data(&mut self) -> Vec<Instrument<TickTime>> {
+        if self.track_state.is_empty() {
+            self.build_track_state();
+        }
+
+        let mut instruments = vec![];
+
+        // Instruments are ordered by track and then by creation within the track.
+        // Instruments still holding straggler events are dropped, as in pretty-midi.
+        for state in &mut self.track_state {
+            let name = state.name.clone().unwrap_or_default();
+            for id in state.instrument_order.drain(..) {
+                let mut instrument = state.channels[id.channel as usize]
+                    .instruments
+                    .remove(&id.program)
+                    .expect("every created instrument is registered with its channel");
+
+                instrument.name = name.clone();
+                instrument.notes.sort_by_key(|note| note.start_time);
+                instruments.push(*instrument);
+            }
+        }
+
+        instruments
+    }
+
+    /// Returns the notes that were still pending at the end of their track,
+    /// ordered by track, channel and start time.
+    pub fn dangling_notes(&mut self) -> Vec<DanglingNote<RealTime>> {
+        if self.track_state.is_empty() {
+            self.build_track_state();
+        }
+
+        let mut dangling: Vec<DanglingNote<RealTime>> = vec![];
+
+        for (track, state) in self.track_state.iter().enumerate() {
+            for channel in &state.channels {
+                let start = dangling.len();
+                for (pitch, pending) in channel.active_notes.iter().enumerate() {
+                    dangling.extend(pending.iter().map(|(start, velocity)| DanglingNote {
+                        track,
+                        channel: channel.channel,
+                        pitch: pitch as Pitch,
+                        velocity: *velocity,
+                        start_time: self.converter.tick_to_time(*start),
+                    }));
+                }
+                dangling[start..].sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+            }
+        }
+
+        dangling
+    }
+
+    pub fn build_metadata(&self) -> Metadata<RealTime> {
+        self.build_tick_metadata().into_real_time(&self.converter)
+    }
+
+    pub fn build_tick_metadata(&self) -> Metadata<TickTime> {
+        Metadata::from_tracks(&self.smf.tracks, self.encoding)
+    }
+
+    /// The time in seconds of the last event in the file.
+    pub fn end_time(&self) -> f64 {
+        self.converter.tick_to_time(get_max_tick(self.smf.tracks.iter()) - 1)
+    }
+
+    pub fn tick_converter(&self) -> &TickConverter {
+        &self.converter
+    }
+
+    /// The number of ticks per beat as given by the header of the file.
+    pub fn resolution(&self) -> u16 {
+        self.resolution
+    }
+}
@@ -0,0 +1,208 @@
+use super::{Note, RealTime, TickConverter, TimeWarp};
+
+/// The result of aligning a performance to a score.
+#[derive(Debug, Clone)]
+pub struct Alignment {
+    /// The warping path as pairs of a score and a performance time, at the centers of the frames.
+    pub path: Vec<(f64, f64)>,
+    /// Maps times of the score onto the performance along the warping path.
+    pub warp: TimeWarp,
+    /// Pairs of indices of a score and a performance note played for it, ordered by the score note.
+    pub notes: Vec<(usize, usize)>,
+    /// The tempo of the performance in beats of the score per minute, starting at each performance time.
+    pub tempo_curve: Vec<(f64, f64)>,
+}
+
+/// Returns how long each pitch class sounds within frames of `hop` seconds, scaled to unit length.
+/// Frames in which nothing sounds are left at zero.
+pub fn chroma_features(notes: &[Note<RealTime>], hop: f64) -> Vec<[f64; 12]> {
+    assert!(hop > 0.0, "The hop of chroma features has to be positive.");
+
+    let end = notes.iter().map(|note| note.end_time).fold(0.0, f64::max);
+    let mut chroma = vec![[0.0; 12]; ((end / hop).ceil() as usize).max(1)];
+    for note in notes {
+        let first = (note.start_time.max(0.0) / hop) as usize;
+        let last = ((note.end_time / hop).ceil() as usize).min(chroma.len());
+        for (frame, bins) in chroma.iter_mut().enumerate().take(last).skip(first) {
+            let (start, end) = (frame as f64 * hop, (frame + 1) as f64 * hop);
+            let overlap = note.end_time.min(end) - note.start_time.max(start);
+            if overlap > 0.0 {
+                bins[note.pitch as usize % 12] += overlap;
+            }
+        }
+    }
+
+    for bins in &mut chroma {
+        let norm = bins.iter().map(|bin| bin * bin).sum::<f64>().sqrt();
+        if norm > 0.0 {
+            bins.iter_mut().for_each(|bin| *bin /= norm);
+        }
+    }
+    chroma
+}
+
+/// The cosine distance of two chroma vectors of unit length, where silence only matches silence.
+fn chroma_distance(a: &[f64; 12], b: &[f64; 12]) -> f64 {
+    let silent = |bins: &[f64; 12]| bins.iter().all(|bin| *bin == 0.0);
+    match (silent(a), silent(b)) {
+        (true, true) => 0.0,
+        (true, false) | (false, true) => 1.0,
+        (false, false) => 1.0 - a.iter().zip(b).map(|(a, b)| a * b).sum::<f64>(),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Diagonal,
+    Up,
+    Left,
+}
+
+/// Finds the warping path of least total distance between two sequences of chroma vectors
+/// by dynamic time warping, from their first to their last frames.
+/// Returns pairs of frame indices into `a` and `b`.
+pub fn dynamic_time_warping(a: &[[f64; 12]], b: &[[f64; 12]]) -> Vec<(usize, usize)> {
+    let (n, m) = (a.len(), b.len());
+    if n == 0 || m == 0 {
+        return vec![];
+    }
+
+    // Only two rows of accumulated distances are kept, along with the step taken into every cell
+    // as the matrix grows quadratically
+    let (mut previous, mut current) = (vec![0f64; m], vec![0f64; m]);
+    let mut steps = vec![Step::Diagonal; n * m];
+    for i in 0..n {
+        for j in 0..m {
+            let (cost, step) = match (i, j) {
+                (0, 0) => (0.0, Step::Diagonal),
+                (0, _) => (current[j - 1], Step::Left),
+                (_, 0) => (previous[0], Step::Up),
+                // Prefers the diagonal on ties
+                _ => [(previous[j - 1], Step::Diagonal), (previous[j], Step::Up), (current[j - 1], Step::Left)]
+                    .into_iter()
+                    .min_by(|(a, _), (b, _)| a.total_cmp(b))
+                    .unwrap(),
+            };
+            current[j] = cost + chroma_distance(&a[i], &b[j]);
+            steps[i * m + j] = step;
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    let mut path = vec![(n - 1, m - 1)];
+    let (mut i, mut j) = (n - 1, m - 1);
+    while i > 0 || j > 0 {
+        match steps[i * m + j] {
+            Step::Diagonal => (i, j) = (i - 1, j - 1),
+            Step::Up => i -= 1,
+            Step::Left => j -= 1,
+        }
+        path.push((i, j));
+    }
+    path.reverse();
+    path
+}
+
+/// Aligns the notes of a performance to the notes of a score. The chroma features of both, in frames
+/// of `hop` seconds, are aligned by dynamic time warping, which predicts when each score note is played.
+/// Every score note is then matched to a performance note of the same pitch starting at most `tolerance`
+/// seconds from the prediction, the closest pairs first. The tempo curve follows the matched notes,
+/// measuring the score in beats with its `converter`.
+pub fn align(
+    score: &[Note<RealTime>],
+    performance: &[Note<RealTime>],
+    converter: &TickConverter,
+    hop: f64,
+    tolerance: f64,
+) -> Alignment {
+    let (score_chroma, performance_chroma) = (chroma_features(score, hop), chroma_features(performance, hop));
+    let frames = dynamic_time_warping(&score_chroma, &performance_chroma);
+    let center = |frame: usize| (frame as f64 + 0.5) * hop;
+
+    // Averages the performance frames aligned to each score frame, pinning down the ends
+    let mut original = vec![0.0];
+    let mut adjusted = vec![0.0];
+    for group in frames.chunk_by(|a, b| a.0 == b.0) {
+        let mean = group.iter().map(|(_, j)| center(*j)).sum::<f64>() / group.len() as f64;
+        original.push(center(group[0].0));
+        adjusted.push(mean);
+    }
+    original.push(score_chroma.len() as f64 * hop);
+    adjusted.push(performance_chroma.len() as f64 * hop);
+    let warp = TimeWarp::new(original, adjusted);
+
+    let notes = match_notes(score, performance, &warp, tolerance);
+    let tempo_curve = tempo_curve(score, performance, &notes, converter);
+    Alignment {
+        path: frames.into_iter().map(|(i, j)| (center(i), center(j))).collect(),
+        warp,
+        notes,
+        tempo_curve,
+    }
+}
+
+fn match_notes(
+    score: &[Note<RealTime>],
+    performance: &[Note<RealTime>],
+    warp: &TimeWarp,
+    tolerance: f64,
+) -> Vec<(usize, usize)> {
+    // The performance notes of each pitch, by their onset
+    let mut by_pitch: Vec<Vec<(f64, usize)>> = vec![vec![]; 128];
+    for (p, played) in performance.iter().enumerate() {
+        by_pitch[played.pitch as usize].push((played.start_time, p));
+    }
+    by_pitch.iter_mut().for_each(|notes| notes.sort_by(|a, b| a.0.total_cmp(&b.0)));
+
+    let mut candidates = vec![];
+    for (s, note) in score.iter().enumerate() {
+        let predicted = warp.apply(note.start_time);
+        let played = &by_pitch[note.pitch as usize];
+        let first = played.partition_point(|(onset, _)| *onset < predicted - tolerance);
+        for (onset, p) in played[first..].iter().take_while(|(onset, _)| *onset <= predicted + tolerance) {
+            candidates.push(((onset - predicted).abs(), s, *p));
+        }
+    }
+    candidates.sort_by(|a, b| a.0.total_cmp(&b.0).then((a.1, a.2).cmp(&(b.1, b.2))));
+
+    let (mut score_matched, mut performance_matched) = (vec![false; score.len()], vec![false; performance.len()]);
+    let mut notes = vec![];
+    for (_, s, p) in candidates {
+        if !score_matched[s] && !performance_matched[p] {
+            score_matched[s] = true;
+            performance_matched[p] = true;
+            notes.push((s, p));
+        }
+    }
+    notes.sort_unstable();
+    notes
+}
+
+/// The tempo between consecutive score onsets, where the notes played for a score onset
+/// count as played at their mean onset. Onsets played out of order are passed over.
+fn tempo_curve(
+    score: &[Note<RealTime>],
+    performance: &[Note<RealTime>],
+    notes: &[(usize, usize)],
+    converter: &TickConverter,
+) -> Vec<(f64, f64)> {
+    let mut onsets: Vec<(f64, f64)> = notes
+        .iter()
+        .map(|(s, p)| (score[*s].start_time, performance[*p].start_time))
+        .collect();
+    onsets.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut curve = vec![];
+    let mut last: Option<(f64, f64)> = None;
+    for group in onsets.chunk_by(|a, b| a.0 == b.0) {
+        let beat = converter.time_to_beat(group[0].0);
+        let played = group.iter().map(|(_, p)| p).sum::<f64>() / group.len() as f64;
+        match last {
+            Some((_, last_played)) if played <= last_played => continue,
+            Some((last_beat, last_played)) => curve.push((last_played, 60.0 * (beat - last_beat) / (played - last_played))),
+            None => {}
+        }
+        last = Some((beat, played));
+    }
+    curve
+}
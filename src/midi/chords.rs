@@ -0,0 +1,172 @@
+use super::{pitch_class_name, Instrument, Pitch, RealTime};
+
+/// The kinds of chords that are recognized, named after their shorthand in Harte's chord syntax.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChordQuality {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Dominant7,
+    Major7,
+    Minor7,
+    HalfDiminished7,
+    Diminished7,
+    Suspended2,
+    Suspended4,
+}
+
+const QUALITIES: [ChordQuality; 11] = [
+    ChordQuality::Major,
+    ChordQuality::Minor,
+    ChordQuality::Diminished,
+    ChordQuality::Augmented,
+    ChordQuality::Dominant7,
+    ChordQuality::Major7,
+    ChordQuality::Minor7,
+    ChordQuality::HalfDiminished7,
+    ChordQuality::Diminished7,
+    ChordQuality::Suspended2,
+    ChordQuality::Suspended4,
+];
+
+/// The bonus given to chords whose root is in the bass, preferring root position
+/// when the same pitch classes make up several chords, like C6 and Am7.
+const ROOT_IN_BASS_BONUS: f64 = 0.01;
+
+impl ChordQuality {
+    /// The intervals above the root, in semitones.
+    pub fn intervals(self) -> &'static [u8] {
+        match self {
+            ChordQuality::Major => &[0, 4, 7],
+            ChordQuality::Minor => &[0, 3, 7],
+            ChordQuality::Diminished => &[0, 3, 6],
+            ChordQuality::Augmented => &[0, 4, 8],
+            ChordQuality::Dominant7 => &[0, 4, 7, 10],
+            ChordQuality::Major7 => &[0, 4, 7, 11],
+            ChordQuality::Minor7 => &[0, 3, 7, 10],
+            ChordQuality::HalfDiminished7 => &[0, 3, 6, 10],
+            ChordQuality::Diminished7 => &[0, 3, 6, 9],
+            ChordQuality::Suspended2 => &[0, 2, 7],
+            ChordQuality::Suspended4 => &[0, 5, 7],
+        }
+    }
+
+    /// The shorthand of Harte's chord syntax, as used by mir_eval.
+    pub fn shorthand(self) -> &'static str {
+        match self {
+            ChordQuality::Major => "maj",
+            ChordQuality::Minor => "min",
+            ChordQuality::Diminished => "dim",
+            ChordQuality::Augmented => "aug",
+            ChordQuality::Dominant7 => "7",
+            ChordQuality::Major7 => "maj7",
+            ChordQuality::Minor7 => "min7",
+            ChordQuality::HalfDiminished7 => "hdim7",
+            ChordQuality::Diminished7 => "dim7",
+            ChordQuality::Suspended2 => "sus2",
+            ChordQuality::Suspended4 => "sus4",
+        }
+    }
+
+    /// The suffix of a lead sheet symbol, like the `m7` of `Am7`.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            ChordQuality::Major => "",
+            ChordQuality::Minor => "m",
+            ChordQuality::Diminished => "dim",
+            ChordQuality::Augmented => "aug",
+            ChordQuality::Dominant7 => "7",
+            ChordQuality::Major7 => "maj7",
+            ChordQuality::Minor7 => "m7",
+            ChordQuality::HalfDiminished7 => "m7b5",
+            ChordQuality::Diminished7 => "dim7",
+            ChordQuality::Suspended2 => "sus2",
+            ChordQuality::Suspended4 => "sus4",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    /// The pitch class of the root.
+    pub root: u8,
+    pub quality: ChordQuality,
+    /// The pitch class of the lowest note.
+    pub bass: u8,
+}
+
+impl Chord {
+    /// The lead sheet symbol of the chord, like `Am7` or `C/E`.
+    pub fn symbol(&self) -> String {
+        let mut symbol = format!("{}{}", pitch_class_name(self.root), self.quality.suffix());
+        if self.bass != self.root {
+            symbol = format!("{}/{}", symbol, pitch_class_name(self.bass));
+        }
+        symbol
+    }
+}
+
+/// A stretch of time labelled with the chord sounding in it, if any.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChordSegment {
+    pub start: f64,
+    pub end: f64,
+    pub chord: Option<Chord>,
+}
+
+/// Finds the chord whose template is closest to how long each pitch class sounds,
+/// measured by the cosine similarity.
+pub fn match_chord(durations: &[f64; 12], bass: u8) -> Option<Chord> {
+    let norm = durations.iter().map(|d| d * d).sum::<f64>().sqrt();
+    if norm == 0.0 {
+        return None;
+    }
+
+    let mut best: Option<(f64, Chord)> = None;
+    for root in 0..12u8 {
+        for quality in QUALITIES {
+            let intervals = quality.intervals();
+            let overlap: f64 = intervals.iter().map(|interval| durations[((root + interval) % 12) as usize]).sum();
+            let mut score = overlap / (norm * (intervals.len() as f64).sqrt());
+            if root == bass {
+                score += ROOT_IN_BASS_BONUS;
+            }
+
+            if best.map(|(best, _)| score > best).unwrap_or(true) {
+                best = Some((score, Chord { root, quality, bass }));
+            }
+        }
+    }
+    best.map(|(_, chord)| chord)
+}
+
+/// Labels the stretches between consecutive `boundaries`, given in seconds, with chords
+/// by matching the pitch content of the pitched instruments against chord templates.
+pub fn estimate_chords(instruments: &[Instrument<RealTime>], boundaries: &[f64]) -> Vec<ChordSegment> {
+    boundaries
+        .windows(2)
+        .map(|w| {
+            let (start, end) = (w[0], w[1]);
+            let mut durations = [0.0; 12];
+            for instrument in instruments {
+                let counts = instrument.pitch_class_durations(start, end);
+                durations.iter_mut().zip(counts).for_each(|(total, count)| *total += count);
+            }
+
+            let bass: Option<Pitch> = instruments
+                .iter()
+                .filter(|instrument| !instrument.is_drum)
+                .flat_map(|instrument| &instrument.notes)
+                .filter(|note| note.start_time < end && note.end_time > start)
+                .map(|note| note.pitch)
+                .min();
+
+            ChordSegment {
+                start,
+                end,
+                chord: bass.and_then(|bass| match_chord(&durations, bass % 12)),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,50 @@
+use super::{Instrument, RealTime};
+
+impl Instrument<RealTime> {
+    /// Removes the notes that end before or at the same time as they start, like pretty-midi.
+    pub fn remove_invalid_notes(&mut self) {
+        self.notes.retain(|note| note.end_time > note.start_time);
+    }
+
+    /// Removes the artefacts commonly found in MIDI corpora:
+    ///  - notes extending past `end_time` are clamped to it and notes starting after it are dropped,
+    ///  - notes of the same pitch starting at the same time are merged into one,
+    ///    keeping the longest duration and the highest velocity,
+    ///  - notes overlapping a later note of the same pitch are truncated to end where it starts,
+    ///  - notes without a duration are dropped.
+    ///
+    /// The remaining notes are ordered by their start time.
+    pub fn clean(&mut self, end_time: Option<f64>) {
+        if let Some(end_time) = end_time {
+            for note in &mut self.notes {
+                note.end_time = note.end_time.min(end_time);
+            }
+        }
+        self.remove_invalid_notes();
+
+        self.notes.sort_by(|a, b| {
+            a.pitch
+                .cmp(&b.pitch)
+                .then(a.start_time.total_cmp(&b.start_time))
+        });
+
+        self.notes.dedup_by(|duplicate, note| {
+            let same = duplicate.pitch == note.pitch && duplicate.start_time == note.start_time;
+            if same {
+                note.end_time = note.end_time.max(duplicate.end_time);
+                note.velocity = note.velocity.max(duplicate.velocity);
+            }
+            same
+        });
+
+        for i in 1..self.notes.len() {
+            let (before, after) = self.notes.split_at_mut(i);
+            let (note, next) = (&mut before[i - 1], &after[0]);
+            if note.pitch == next.pitch && note.end_time > next.start_time {
+                note.end_time = next.start_time;
+            }
+        }
+
+        self.notes.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+    }
+}
@@ -0,0 +1,81 @@
+use super::{Instrument, MidiTime, RealTime, TempoChange, TickConverter};
+
+/// Adds the `others`, delayed by `offset` seconds, to the events, keeping them ordered by time.
+/// Events at the same time keep their order, with the existing events first.
+pub fn merge_event_times<E: Clone>(
+    events: &mut Vec<E>,
+    others: &[E],
+    time: impl Fn(&mut E) -> &mut f64,
+    offset: f64,
+) {
+    let mut timed: Vec<(f64, E)> = events.drain(..).map(|mut event| (*time(&mut event), event)).collect();
+    timed.extend(others.iter().cloned().map(|mut event| {
+        let t = time(&mut event);
+        *t += offset;
+        (*t, event)
+    }));
+    timed.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+    events.extend(timed.into_iter().map(|(_, event)| event));
+}
+
+impl Instrument<RealTime> {
+    /// Whether the events of both instruments belong to the same part,
+    /// meaning they share their program, drum flag and name.
+    pub fn same_part(&self, other: &Self) -> bool {
+        self.program == other.program && self.is_drum == other.is_drum && self.name == other.name
+    }
+
+    /// Adds the events of `other`, delayed by `offset` seconds, to the instrument.
+    pub fn merge(&mut self, other: &Self, offset: f64) {
+        self.notes.extend(other.notes.iter().map(|note| super::Note {
+            start_time: note.start_time + offset,
+            end_time: note.end_time + offset,
+            ..*note
+        }));
+        self.notes.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+        merge_event_times(&mut self.pitch_bends, &other.pitch_bends, |bend| &mut bend.time, offset);
+        merge_event_times(&mut self.control_changes, &other.control_changes, |cc| &mut cc.time, offset);
+    }
+}
+
+/// Combines two lists of instruments, delaying the `others` by `offset` seconds.
+/// The instruments of the same part are merged into one, the others are added at the end.
+pub fn merge_instruments(
+    instruments: &[Instrument<RealTime>],
+    others: &[Instrument<RealTime>],
+    offset: f64,
+) -> Vec<Instrument<RealTime>> {
+    let mut merged = instruments.to_vec();
+    for other in others {
+        match merged.iter_mut().find(|instrument| instrument.same_part(other)) {
+            Some(instrument) => instrument.merge(other, offset),
+            None => merged.push(other.clone().map_time(|time| time + offset)),
+        }
+    }
+    merged
+}
+
+impl TickConverter {
+    /// Appends the tempo map of `other` starting at the tick `offset`,
+    /// rescaling its ticks if the resolutions differ.
+    pub fn concat(&self, other: &TickConverter, offset: MidiTime) -> TickConverter {
+        let scale = self.resolution() as f64 / other.resolution() as f64;
+
+        let mut tempo_map: Vec<TempoChange> = self
+            .tempo_map
+            .iter()
+            .filter(|(tick, _)| *tick < offset)
+            .copied()
+            .collect();
+        for (tick, tempo) in &other.tempo_map {
+            let tick = offset + (*tick as f64 * scale).round() as MidiTime;
+            match tempo_map.last_mut() {
+                Some(last) if last.0 == tick => *last = (tick, *tempo),
+                _ => tempo_map.push((tick, *tempo)),
+            }
+        }
+        tempo_map.dedup_by_key(|(_, tempo)| *tempo);
+
+        TickConverter::new(&tempo_map, self.resolution())
+    }
+}
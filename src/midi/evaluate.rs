@@ -0,0 +1,403 @@
+use super::{Note, RealTime};
+use std::collections::HashMap;
+
+/// Distances are rounded to this many decimals before comparing them to a tolerance,
+/// like `N_DECIMALS` of `mir_eval`, so that a distance of exactly the tolerance is not lost to rounding errors.
+const DISTANCE_DECIMALS: i32 = 4;
+
+/// When reference and estimated notes count as the same note, with the defaults of
+/// `mir_eval.transcription.precision_recall_f1_overlap`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoteTolerances {
+    /// The largest distance between the onsets, in seconds.
+    pub onset: f64,
+    /// The largest distance between the pitches, in cents.
+    pub pitch: f64,
+    /// The largest distance between the offsets as a fraction of the duration of the reference note,
+    /// or `None` to ignore offsets.
+    pub offset_ratio: Option<f64>,
+    /// The smallest tolerance for the distance between the offsets, in seconds.
+    pub offset_min: f64,
+    /// Whether distances have to be strictly below their tolerance.
+    pub strict: bool,
+}
+
+impl Default for NoteTolerances {
+    fn default() -> Self {
+        NoteTolerances {
+            onset: 0.05,
+            pitch: 50.0,
+            offset_ratio: Some(0.2),
+            offset_min: 0.05,
+            strict: false,
+        }
+    }
+}
+
+impl NoteTolerances {
+    fn within(&self, distance: f64, tolerance: f64) -> bool {
+        if self.strict {
+            distance < tolerance
+        } else {
+            distance <= tolerance
+        }
+    }
+
+    fn onsets_match(&self, reference: &Note<RealTime>, estimate: &Note<RealTime>) -> bool {
+        self.within(distance(reference.start_time, estimate.start_time), self.onset)
+    }
+
+    fn offset_tolerance(&self, reference: &Note<RealTime>, ratio: f64) -> f64 {
+        (ratio * (reference.end_time - reference.start_time)).max(self.offset_min)
+    }
+
+    fn offsets_match(&self, reference: &Note<RealTime>, estimate: &Note<RealTime>, ratio: f64) -> bool {
+        self.within(distance(reference.end_time, estimate.end_time), self.offset_tolerance(reference, ratio))
+    }
+
+    fn notes_match(&self, reference: &Note<RealTime>, estimate: &Note<RealTime>) -> bool {
+        let cents = 100.0 * (reference.pitch as f64 - estimate.pitch as f64).abs();
+        self.onsets_match(reference, estimate)
+            && self.within(cents, self.pitch)
+            && self.offset_ratio.is_none_or(|ratio| self.offsets_match(reference, estimate, ratio))
+    }
+}
+
+fn distance(a: f64, b: f64) -> f64 {
+    let scale = 10f64.powi(DISTANCE_DECIMALS);
+    ((a - b).abs() * scale).round_ties_even() / scale
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoteScores {
+    pub precision: f64,
+    pub recall: f64,
+    pub f_measure: f64,
+    /// The mean ratio of the overlap of the matched notes to their combined extent.
+    pub average_overlap_ratio: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameScores {
+    pub precision: f64,
+    pub recall: f64,
+    pub f_measure: f64,
+    /// The number of correctly active frames over the number of frames active in either.
+    pub accuracy: f64,
+}
+
+/// The weighted harmonic mean of precision and recall, like `mir_eval.util.f_measure`.
+pub fn f_measure(precision: f64, recall: f64, beta: f64) -> f64 {
+    if precision == 0.0 && recall == 0.0 {
+        return 0.0;
+    }
+    (1.0 + beta * beta) * precision * recall / (beta * beta * precision + recall)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Predecessor {
+    Free,
+    Reference(usize),
+}
+
+/// The state of the Hopcroft–Karp search of `mir_eval.util._bipartite_match`,
+/// with estimated notes on one side and reference notes on the other.
+struct Matcher {
+    /// The reference notes that each estimated note may match.
+    edges: Vec<Vec<usize>>,
+    /// The estimated note matched to each reference note.
+    matching: Vec<Option<usize>>,
+    predecessors: Vec<Option<Predecessor>>,
+    layered: Vec<Option<Vec<usize>>>,
+}
+
+impl Matcher {
+    /// Finds an alternating path back from the reference note `v`, flipping the matching along it.
+    fn augment(&mut self, v: usize) -> bool {
+        let Some(candidates) = self.layered[v].take() else {
+            return false;
+        };
+        for u in candidates {
+            if let Some(predecessor) = self.predecessors[u].take() {
+                let found = match predecessor {
+                    Predecessor::Free => true,
+                    Predecessor::Reference(previous) => self.augment(previous),
+                };
+                if found {
+                    self.matching[v] = Some(u);
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Finds a maximum matching between reference and estimated notes where `hit` tells which pairs may match.
+/// Only pairs whose `time` lies at most the `reach` of the reference note apart are tried.
+/// The search visits the pairs in the same order as `mir_eval`, so that it picks the same matching.
+/// Returns pairs of indices of a reference and an estimated note, ordered by the reference note.
+fn match_by(
+    reference: &[Note<RealTime>],
+    estimate: &[Note<RealTime>],
+    time: impl Fn(&Note<RealTime>) -> f64,
+    reach: impl Fn(&Note<RealTime>) -> f64,
+    hit: impl Fn(&Note<RealTime>, &Note<RealTime>) -> bool,
+) -> Vec<(usize, usize)> {
+    let mut by_time: Vec<(f64, usize)> = estimate.iter().map(&time).zip(0..).collect();
+    by_time.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+    // The estimated notes in the order they are first hit
+    let mut order = vec![];
+    let mut edges = vec![vec![]; estimate.len()];
+    for (v, note) in reference.iter().enumerate() {
+        let (time, reach) = (time(note), reach(note));
+        let first = by_time.partition_point(|(other, _)| *other < time && distance(*other, time) > reach);
+        let last = by_time.partition_point(|(other, _)| *other <= time || distance(*other, time) <= reach);
+        let mut candidates: Vec<usize> = by_time[first..last].iter().map(|(_, u)| *u).collect();
+        candidates.sort_unstable();
+
+        for u in candidates {
+            if hit(note, &estimate[u]) {
+                if edges[u].is_empty() {
+                    order.push(u);
+                }
+                edges[u].push(v);
+            }
+        }
+    }
+
+    let mut matcher = Matcher {
+        edges,
+        matching: vec![None; reference.len()],
+        predecessors: vec![],
+        layered: vec![],
+    };
+    // Starts from a greedy matching
+    for &u in &order {
+        if let Some(&v) = matcher.edges[u].iter().find(|v| matcher.matching[**v].is_none()) {
+            matcher.matching[v] = Some(u);
+        }
+    }
+
+    loop {
+        matcher.layered = vec![None; reference.len()];
+        matcher.predecessors = vec![None; estimate.len()];
+        for &u in &order {
+            matcher.predecessors[u] = Some(Predecessor::Free);
+        }
+        for u in matcher.matching.iter().flatten() {
+            matcher.predecessors[*u] = None;
+        }
+
+        let mut layer: Vec<usize> = order.iter().copied().filter(|u| matcher.predecessors[*u].is_some()).collect();
+        let mut unmatched = vec![];
+        while !layer.is_empty() && unmatched.is_empty() {
+            let mut next: Vec<usize> = vec![];
+            let mut next_predecessors: HashMap<usize, Vec<usize>> = HashMap::new();
+            for &u in &layer {
+                for &v in &matcher.edges[u] {
+                    if matcher.layered[v].is_none() {
+                        next_predecessors
+                            .entry(v)
+                            .or_insert_with(|| {
+                                next.push(v);
+                                vec![]
+                            })
+                            .push(u);
+                    }
+                }
+            }
+
+            layer.clear();
+            for v in next {
+                matcher.layered[v] = next_predecessors.remove(&v);
+                match matcher.matching[v] {
+                    Some(u) => {
+                        layer.push(u);
+                        matcher.predecessors[u] = Some(Predecessor::Reference(v));
+                    }
+                    None => unmatched.push(v),
+                }
+            }
+        }
+
+        if unmatched.is_empty() {
+            break;
+        }
+        for v in unmatched {
+            matcher.augment(v);
+        }
+    }
+
+    matcher
+        .matching
+        .iter()
+        .enumerate()
+        .filter_map(|(v, u)| Some((v, (*u)?)))
+        .collect()
+}
+
+/// Matches notes of the same pitch with close onsets and, unless the offset ratio is `None`, offsets,
+/// like `mir_eval.transcription.match_notes`. Returns pairs of indices of a reference and an estimated note.
+pub fn match_notes(
+    reference: &[Note<RealTime>],
+    estimate: &[Note<RealTime>],
+    tolerances: &NoteTolerances,
+) -> Vec<(usize, usize)> {
+    match_by(
+        reference,
+        estimate,
+        |note| note.start_time,
+        |_| tolerances.onset,
+        |reference, estimate| tolerances.notes_match(reference, estimate),
+    )
+}
+
+/// Matches notes with close onsets regardless of their pitch, like `mir_eval.transcription.match_note_onsets`.
+pub fn match_note_onsets(
+    reference: &[Note<RealTime>],
+    estimate: &[Note<RealTime>],
+    tolerances: &NoteTolerances,
+) -> Vec<(usize, usize)> {
+    match_by(
+        reference,
+        estimate,
+        |note| note.start_time,
+        |_| tolerances.onset,
+        |reference, estimate| tolerances.onsets_match(reference, estimate),
+    )
+}
+
+/// Matches notes with close offsets regardless of their pitch, like `mir_eval.transcription.match_note_offsets`.
+/// Matches nothing when the offset ratio is `None`.
+pub fn match_note_offsets(
+    reference: &[Note<RealTime>],
+    estimate: &[Note<RealTime>],
+    tolerances: &NoteTolerances,
+) -> Vec<(usize, usize)> {
+    let Some(ratio) = tolerances.offset_ratio else {
+        return vec![];
+    };
+    match_by(
+        reference,
+        estimate,
+        |note| note.end_time,
+        |note| tolerances.offset_tolerance(note, ratio),
+        |reference, estimate| tolerances.offsets_match(reference, estimate, ratio),
+    )
+}
+
+/// Matches notes like [`match_notes`] and keeps the pairs whose velocities agree within `velocity_tolerance`,
+/// like `mir_eval.transcription_velocity.match_notes`. The reference velocities are scaled to the range
+/// from zero to one and the estimated velocities are mapped onto them by a linear regression over the matches.
+pub fn match_notes_with_velocity(
+    reference: &[Note<RealTime>],
+    estimate: &[Note<RealTime>],
+    tolerances: &NoteTolerances,
+    velocity_tolerance: f64,
+) -> Vec<(usize, usize)> {
+    let matching = match_notes(reference, estimate, tolerances);
+    if matching.is_empty() {
+        return matching;
+    }
+
+    let low = reference.iter().map(|note| note.velocity).min().unwrap_or(0) as f64;
+    let high = reference.iter().map(|note| note.velocity).max().unwrap_or(0) as f64;
+    let range = (high - low).max(1.0);
+    let pairs: Vec<(f64, f64)> = matching
+        .iter()
+        .map(|(v, u)| ((reference[*v].velocity as f64 - low) / range, estimate[*u].velocity as f64))
+        .collect();
+
+    // The least squares fit of the reference velocities by the estimated ones, which
+    // predicts the mean reference velocity when the estimated velocities are all the same
+    let count = pairs.len() as f64;
+    let mean_reference = pairs.iter().map(|(r, _)| r).sum::<f64>() / count;
+    let mean_estimate = pairs.iter().map(|(_, e)| e).sum::<f64>() / count;
+    let variance = pairs.iter().map(|(_, e)| (e - mean_estimate).powi(2)).sum::<f64>();
+    let covariance = pairs.iter().map(|(r, e)| (r - mean_reference) * (e - mean_estimate)).sum::<f64>();
+    let slope = if variance > 0.0 { covariance / variance } else { 0.0 };
+
+    matching
+        .into_iter()
+        .zip(pairs)
+        .filter(|(_, (r, e))| (mean_reference + slope * (e - mean_estimate) - r).abs() < velocity_tolerance)
+        .map(|(pair, _)| pair)
+        .collect()
+}
+
+/// The mean ratio of the overlap of the matched notes to their combined extent,
+/// like `mir_eval.transcription.average_overlap_ratio`.
+pub fn average_overlap_ratio(
+    reference: &[Note<RealTime>],
+    estimate: &[Note<RealTime>],
+    matching: &[(usize, usize)],
+) -> f64 {
+    if matching.is_empty() {
+        return 0.0;
+    }
+    let ratios = matching.iter().map(|(v, u)| {
+        let (reference, estimate) = (&reference[*v], &estimate[*u]);
+        let overlap = reference.end_time.min(estimate.end_time) - reference.start_time.max(estimate.start_time);
+        overlap / (reference.end_time.max(estimate.end_time) - reference.start_time.min(estimate.start_time))
+    });
+    ratios.sum::<f64>() / matching.len() as f64
+}
+
+/// Scores a matching between reference and estimated notes.
+/// Everything scores zero when either has no notes, like in `mir_eval`.
+pub fn note_scores(
+    reference: &[Note<RealTime>],
+    estimate: &[Note<RealTime>],
+    matching: &[(usize, usize)],
+    beta: f64,
+) -> NoteScores {
+    if reference.is_empty() || estimate.is_empty() {
+        return NoteScores { precision: 0.0, recall: 0.0, f_measure: 0.0, average_overlap_ratio: 0.0 };
+    }
+
+    let precision = matching.len() as f64 / estimate.len() as f64;
+    let recall = matching.len() as f64 / reference.len() as f64;
+    NoteScores {
+        precision,
+        recall,
+        f_measure: f_measure(precision, recall, beta),
+        average_overlap_ratio: average_overlap_ratio(reference, estimate, matching),
+    }
+}
+
+/// Marks the frames of `1 / fs` seconds in which each of the 128 pitches sounds,
+/// counting the frames from the one holding the start of a note up to the one holding its end.
+pub fn active_frames(notes: &[Note<RealTime>], fs: f64) -> Vec<Vec<bool>> {
+    let frame = |time: f64| (time * fs).max(0.0) as usize;
+    let frames = notes.iter().map(|note| frame(note.end_time)).max().unwrap_or(0);
+
+    let mut roll = vec![vec![false; frames]; 128];
+    for note in notes {
+        roll[note.pitch as usize][frame(note.start_time)..frame(note.end_time).max(frame(note.start_time))]
+            .fill(true);
+    }
+    roll
+}
+
+/// Compares the active frames of two piano rolls, treating missing rows and frames as silent.
+pub fn frame_scores(reference: &[Vec<bool>], estimate: &[Vec<bool>], beta: f64) -> FrameScores {
+    let count = |roll: &[Vec<bool>]| roll.iter().flatten().filter(|active| **active).count();
+    let correct: usize = reference
+        .iter()
+        .zip(estimate)
+        .map(|(reference, estimate)| reference.iter().zip(estimate).filter(|(r, e)| **r && **e).count())
+        .sum();
+    let (reference, estimate) = (count(reference), count(estimate));
+
+    let ratio = |count: usize, total: usize| if total > 0 { count as f64 / total as f64 } else { 0.0 };
+    let precision = ratio(correct, estimate);
+    let recall = ratio(correct, reference);
+    FrameScores {
+        precision,
+        recall,
+        f_measure: f_measure(precision, recall, beta),
+        accuracy: ratio(correct, reference + estimate - correct),
+    }
+}
@@ -0,0 +1,94 @@
+use super::{Instrument, RealTime};
+
+pub type PitchClassHistogram = [f64; 12];
+pub type PitchClassTransitions = [[f64; 12]; 12];
+
+impl Instrument<RealTime> {
+    /// Returns the distinct times at which notes start, in order.
+    pub fn onsets(&self) -> Vec<f64> {
+        let mut onsets: Vec<_> = self.notes.iter().map(|note| note.start_time).collect();
+        onsets.sort_by(f64::total_cmp);
+        onsets.dedup();
+        onsets
+    }
+
+    /// Returns the time of the last event, which is either the end of a note,
+    /// a pitch bend or a control change, or zero without any events.
+    pub fn end_time(&self) -> f64 {
+        self.notes
+            .iter()
+            .map(|note| note.end_time)
+            .chain(self.pitch_bends.iter().map(|bend| bend.time))
+            .chain(self.control_changes.iter().map(|cc| cc.time))
+            .fold(0.0, f64::max)
+    }
+
+    /// Counts the notes of each pitch class like pretty-midi, weighting them by their duration
+    /// and velocity when asked to. When normalized the histogram sums to one.
+    /// Drum instruments have an empty histogram.
+    pub fn pitch_class_histogram(&self, use_duration: bool, use_velocity: bool, normalize: bool) -> PitchClassHistogram {
+        let mut histogram = [0.0; 12];
+        if self.is_drum {
+            return histogram;
+        }
+
+        for note in &self.notes {
+            let mut weight = 1.0;
+            if use_duration {
+                weight *= note.end_time - note.start_time;
+            }
+            if use_velocity {
+                weight *= note.velocity as f64;
+            }
+            histogram[note.pitch as usize % 12] += weight;
+        }
+
+        if normalize {
+            normalize_histogram(&mut histogram);
+        }
+        histogram
+    }
+
+    /// Counts the transitions between the pitch classes of notes like pretty-midi,
+    /// where a note ending less than `time_thresh` seconds from the start of another
+    /// counts as a transition from its pitch class to the pitch class of the other.
+    /// When normalized the matrix sums to one. Drum instruments have no transitions.
+    pub fn pitch_class_transition_matrix(&self, normalize: bool, time_thresh: f64) -> PitchClassTransitions {
+        let mut matrix = [[0.0; 12]; 12];
+        if self.is_drum || self.notes.len() <= 1 {
+            return matrix;
+        }
+
+        let mut starts: Vec<_> = self.notes.iter().map(|note| (note.start_time, note.pitch as usize % 12)).collect();
+        starts.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        for note in &self.notes {
+            let first = starts.partition_point(|(start, _)| note.end_time - start >= time_thresh);
+            let source = note.pitch as usize % 12;
+            for (_, target) in starts[first..].iter().take_while(|(start, _)| start - note.end_time < time_thresh) {
+                matrix[source][*target] += 1.0;
+            }
+        }
+
+        if normalize {
+            normalize_transitions(&mut matrix);
+        }
+        matrix
+    }
+}
+
+/// Scales the histogram to sum to one, unless it is empty.
+pub fn normalize_histogram(histogram: &mut PitchClassHistogram) {
+    let total: f64 = histogram.iter().sum();
+    if total > 0.0 {
+        histogram.iter_mut().for_each(|count| *count /= total);
+    }
+}
+
+/// Scales the transition matrix to sum to one, unless it is empty.
+pub fn normalize_transitions(matrix: &mut PitchClassTransitions) {
+    let total: f64 = matrix.iter().flatten().sum();
+    if total > 0.0 {
+        matrix.iter_mut().flatten().for_each(|count| *count /= total);
+    }
+}
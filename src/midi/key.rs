@@ -0,0 +1,146 @@
+use super::{Instrument, PitchClassHistogram, RealTime};
+
+const KEY_NAMES: [&str; 12] = ["C", "Db", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B"];
+
+/// The weights of the pitch classes of a major and a minor key, starting from the tonic.
+type Profile = ([f64; 12], [f64; 12]);
+
+const KRUMHANSL_KESSLER: Profile = (
+    [6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88],
+    [6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17],
+);
+const TEMPERLEY: Profile = (
+    [0.748, 0.060, 0.488, 0.082, 0.670, 0.460, 0.096, 0.715, 0.104, 0.366, 0.057, 0.400],
+    [0.712, 0.084, 0.474, 0.618, 0.049, 0.460, 0.105, 0.747, 0.404, 0.067, 0.133, 0.330],
+);
+const ALBRECHT_SHANAHAN: Profile = (
+    [0.238, 0.006, 0.111, 0.006, 0.137, 0.094, 0.016, 0.214, 0.009, 0.080, 0.008, 0.081],
+    [0.220, 0.006, 0.104, 0.123, 0.019, 0.103, 0.012, 0.214, 0.062, 0.022, 0.061, 0.052],
+);
+
+/// The key profiles that pitch class distributions are compared against.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum KeyProfile {
+    /// The probe tone ratings of Krumhansl and Kessler, as used by the Krumhansl–Schmuckler algorithm.
+    #[default]
+    KrumhanslSchmuckler,
+    /// The Kostka–Payne corpus profiles of Temperley.
+    Temperley,
+    /// The classical corpus profiles of Albrecht and Shanahan.
+    Albrecht,
+}
+
+impl KeyProfile {
+    fn weights(self) -> &'static Profile {
+        match self {
+            KeyProfile::KrumhanslSchmuckler => &KRUMHANSL_KESSLER,
+            KeyProfile::Temperley => &TEMPERLEY,
+            KeyProfile::Albrecht => &ALBRECHT_SHANAHAN,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyEstimate {
+    /// The key in the range `0..24`, like [`super::KeySignature::key_number`].
+    pub key_number: u8,
+    /// The correlation of the pitch class distribution with the profile of the key.
+    pub correlation: f64,
+}
+
+/// Returns the name of a pitch class, spelled like the tonics of pretty-midi's key names.
+pub fn pitch_class_name(pitch_class: u8) -> &'static str {
+    KEY_NAMES[pitch_class as usize % 12]
+}
+
+/// Returns the name of a key number like `pretty_midi.key_number_to_key_name`, e.g. `"Bb minor"`.
+pub fn key_number_to_key_name(key_number: u8) -> String {
+    let mode = if key_number < 12 { "Major" } else { "minor" };
+    format!("{} {}", pitch_class_name(key_number), mode)
+}
+
+fn correlation(a: &[f64; 12], b: impl Fn(usize) -> f64) -> f64 {
+    let mean_a = a.iter().sum::<f64>() / 12.0;
+    let mean_b = (0..12).map(&b).sum::<f64>() / 12.0;
+
+    let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+    for (i, a) in a.iter().enumerate() {
+        let (da, db) = (a - mean_a, b(i) - mean_b);
+        covariance += da * db;
+        variance_a += da * da;
+        variance_b += db * db;
+    }
+    covariance / (variance_a * variance_b).sqrt()
+}
+
+/// Finds the key whose profile correlates best with the pitch class distribution.
+/// Distributions giving equal weight to all pitch classes, including empty ones, have no key.
+pub fn estimate_key(distribution: &PitchClassHistogram, profile: KeyProfile) -> Option<KeyEstimate> {
+    if distribution.iter().all(|weight| *weight == distribution[0]) {
+        return None;
+    }
+
+    let (major, minor) = profile.weights();
+    (0..24u8)
+        .map(|key_number| {
+            let tonic = key_number as usize % 12;
+            let weights = if key_number < 12 { major } else { minor };
+            KeyEstimate {
+                key_number,
+                correlation: correlation(distribution, |pitch_class| weights[(pitch_class + 12 - tonic) % 12]),
+            }
+        })
+        .max_by(|a, b| a.correlation.total_cmp(&b.correlation))
+}
+
+impl Instrument<RealTime> {
+    /// Returns how long the notes of each pitch class sound between `start` and `end`.
+    /// Drum instruments have no pitch classes.
+    pub fn pitch_class_durations(&self, start: f64, end: f64) -> PitchClassHistogram {
+        let mut durations = [0.0; 12];
+        if self.is_drum {
+            return durations;
+        }
+
+        for note in &self.notes {
+            let duration = note.end_time.min(end) - note.start_time.max(start);
+            if duration > 0.0 {
+                durations[note.pitch as usize % 12] += duration;
+            }
+        }
+        durations
+    }
+}
+
+/// Estimates the key from how long the notes of each pitch class sound between `start` and `end`.
+pub fn estimate_key_between(
+    instruments: &[Instrument<RealTime>],
+    start: f64,
+    end: f64,
+    profile: KeyProfile,
+) -> Option<KeyEstimate> {
+    let mut durations = [0.0; 12];
+    for instrument in instruments {
+        let counts = instrument.pitch_class_durations(start, end);
+        durations.iter_mut().zip(counts).for_each(|(total, count)| *total += count);
+    }
+    estimate_key(&durations, profile)
+}
+
+/// Estimates the key within windows of `window` seconds, starting every `hop` seconds until `end`.
+/// Returns the start of each window along with its key, if it has one.
+pub fn estimate_key_curve(
+    instruments: &[Instrument<RealTime>],
+    window: f64,
+    hop: f64,
+    end: f64,
+    profile: KeyProfile,
+) -> Vec<(f64, Option<KeyEstimate>)> {
+    assert!(window > 0.0 && hop > 0.0, "The window and hop of a key curve have to be positive.");
+
+    (0..)
+        .map(|i| i as f64 * hop)
+        .take_while(|start| *start < end)
+        .map(|start| (start, estimate_key_between(instruments, start, start + window, profile)))
+        .collect()
+}
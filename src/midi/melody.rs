@@ -0,0 +1,129 @@
+use super::{separate_voices, Instrument, Note, ProgramNo, RealTime};
+use std::collections::HashSet;
+
+/// Words in the name of an instrument suggesting that it carries the melody.
+const MELODY_NAMES: [&str; 7] = ["melody", "melodie", "vocal", "voice", "lead", "solo", "sing"];
+/// Words in the name of an instrument suggesting that it accompanies the melody.
+const ACCOMPANIMENT_NAMES: [&str; 6] = ["bass", "drum", "pad", "chord", "accomp", "rhythm"];
+
+/// A voice needs more than one in this many of the notes of the fullest voice to carry the melody.
+const MIN_VOICE_SHARE: usize = 4;
+
+/// The ways of finding the melody of a file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MelodyStrategy {
+    /// Takes the highest note sounding at any time, from all pitched instruments.
+    #[default]
+    Skyline,
+    /// Takes the instrument that looks most like a melody, judging by its name,
+    /// polyphony, register and range, and reduces it to its highest notes.
+    Track,
+    /// Separates the notes of all pitched instruments into voices and takes the highest voice,
+    /// passing over voices with at most a quarter of the notes of the fullest voice.
+    Voice,
+}
+
+/// Keeps the highest of the notes starting together and cuts notes short
+/// when a note at least as high starts. Notes starting below a sounding note are left out.
+fn skyline(mut notes: Vec<Note<RealTime>>) -> Vec<Note<RealTime>> {
+    notes.sort_by(|a, b| a.start_time.total_cmp(&b.start_time).then(b.pitch.cmp(&a.pitch)));
+
+    let mut melody: Vec<Note<RealTime>> = Vec::with_capacity(notes.len());
+    for note in notes {
+        match melody.last_mut() {
+            Some(last) if last.start_time == note.start_time => {}
+            Some(last) if last.end_time > note.start_time && last.pitch > note.pitch => {}
+            Some(last) => {
+                last.end_time = last.end_time.min(note.start_time);
+                melody.push(note);
+            }
+            None => melody.push(note),
+        }
+    }
+    melody
+}
+
+/// How much an instrument looks like it carries the melody, the higher the more.
+fn melody_score(instrument: &Instrument<RealTime>) -> f64 {
+    let name = instrument.name.to_lowercase();
+    let mut score = 0.0;
+    if MELODY_NAMES.iter().any(|word| name.contains(word)) {
+        score += 2.0;
+    }
+    if ACCOMPANIMENT_NAMES.iter().any(|word| name.contains(word)) {
+        score -= 2.0;
+    }
+    // The bass family of General MIDI
+    if (32..40).contains(&instrument.program) {
+        score -= 1.0;
+    }
+
+    let notes = &instrument.notes;
+    let count = notes.len() as f64;
+    // Melodies are mostly monophonic
+    let overlapping = notes.windows(2).filter(|w| w[1].start_time < w[0].end_time).count() as f64;
+    score += 1.0 - overlapping / count;
+    // and sit in the middle to upper register within a couple of octaves
+    let mean_pitch = notes.iter().map(|note| note.pitch as f64).sum::<f64>() / count;
+    score += ((mean_pitch - 60.0) / 24.0).clamp(-1.0, 1.0) * 0.5;
+    let low = notes.iter().map(|note| note.pitch).min().unwrap_or(0);
+    let high = notes.iter().map(|note| note.pitch).max().unwrap_or(0);
+    if high - low > 36 {
+        score -= 0.5;
+    }
+    score
+}
+
+/// Returns the melody of the instruments as a monophonic instrument.
+/// The melody takes the program of the instrument playing most of its notes.
+pub fn extract_melody(instruments: &[Instrument<RealTime>], strategy: MelodyStrategy) -> Instrument<RealTime> {
+    let pitched: Vec<_> = instruments
+        .iter()
+        .filter(|instrument| !instrument.is_drum && !instrument.notes.is_empty())
+        .collect();
+
+    if strategy == MelodyStrategy::Track {
+        return match pitched.iter().max_by(|a, b| melody_score(a).total_cmp(&melody_score(b))) {
+            Some(instrument) => Instrument {
+                name: instrument.name.clone(),
+                notes: skyline(instrument.notes.clone()),
+                ..Instrument::new(instrument.program, false)
+            },
+            None => Instrument { name: "Melody".to_string(), ..Instrument::new(0, false) },
+        };
+    }
+
+    let all_notes: Vec<_> = pitched.iter().flat_map(|instrument| instrument.notes.iter().cloned()).collect();
+    let notes = match strategy {
+        MelodyStrategy::Voice => {
+            // The highest voice, passing over voices with only a few notes
+            let voices = separate_voices(&all_notes, usize::MAX);
+            let most_notes = voices.iter().map(Vec::len).max().unwrap_or(0);
+            voices
+                .into_iter()
+                .find(|voice| voice.len() * MIN_VOICE_SHARE > most_notes)
+                .unwrap_or_default()
+        }
+        _ => skyline(all_notes),
+    };
+
+    // The instrument sharing the most notes with the melody, ignoring where they were cut short
+    let onsets: HashSet<_> = notes.iter().map(|note| (note.pitch, note.start_time.to_bits())).collect();
+    let program: ProgramNo = pitched
+        .iter()
+        .max_by_key(|instrument| {
+            instrument
+                .notes
+                .iter()
+                .filter(|note| onsets.contains(&(note.pitch, note.start_time.to_bits())))
+                .count()
+        })
+        .map(|instrument| instrument.program)
+        .unwrap_or(0);
+
+    Instrument {
+        name: "Melody".to_string(),
+        notes,
+        ..Instrument::new(program, false)
+    }
+}
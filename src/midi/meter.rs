@@ -0,0 +1,138 @@
+use super::{TickTime, TimeSignature};
+
+const DEFAULT_NUMERATOR: u8 = 4;
+const DEFAULT_DENOMINATOR: u8 = 4;
+
+/// A stretch of bars sharing the same time signature.
+#[derive(Debug, Clone)]
+struct Meter {
+    start: f64,
+    /// Index of the first bar of the meter.
+    bar: u32,
+    numerator: u8,
+    ticks_per_beat: f64,
+}
+
+impl Meter {
+    fn ticks_per_bar(&self) -> f64 {
+        self.numerator as f64 * self.ticks_per_beat
+    }
+}
+
+/// A position given as a bar, a beat within that bar and a tick within that beat.
+/// Bars and beats count from 1, ticks from 0, and beats are measured in
+/// the note value of the time signature's denominator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BarBeatTick {
+    pub bar: u32,
+    pub beat: u32,
+    pub tick: f64,
+}
+
+/// Converts between ticks and bar/beat/tick positions according to the time signature changes.
+/// A time signature change that does not fall on a bar line starts a new bar,
+/// leaving the previous bar incomplete.
+#[derive(Debug, Clone)]
+pub struct MeterConverter {
+    meters: Vec<Meter>,
+}
+
+impl MeterConverter {
+    /// Creates a converter from time signature changes ordered by time.
+    /// Until the first change the time signature is 4/4.
+    pub fn new(time_signatures: &[TimeSignature<TickTime>], resolution: u16) -> Self {
+        let ticks_per_beat = |denominator: u8| resolution as f64 * 4.0 / denominator as f64;
+
+        let mut meters = vec![Meter {
+            start: 0.0,
+            bar: 0,
+            numerator: DEFAULT_NUMERATOR,
+            ticks_per_beat: ticks_per_beat(DEFAULT_DENOMINATOR),
+        }];
+
+        for ts in time_signatures {
+            let last = meters.last().unwrap();
+            let start = ts.time as f64;
+            let bar = last.bar + ((start - last.start) / last.ticks_per_bar()).ceil() as u32;
+            let meter = Meter {
+                start,
+                bar,
+                numerator: ts.numerator,
+                ticks_per_beat: ticks_per_beat(ts.denominator),
+            };
+
+            // A change on the same tick replaces the previous one
+            if last.start == start {
+                *meters.last_mut().unwrap() = meter;
+            } else {
+                meters.push(meter);
+            }
+        }
+
+        MeterConverter { meters }
+    }
+
+    fn meter_at(&self, tick: f64) -> &Meter {
+        let i = self.meters.partition_point(|meter| meter.start <= tick).max(1) - 1;
+        &self.meters[i]
+    }
+
+    /// Returns the index, counting from 0, of the bar containing the given tick.
+    pub fn measure_index(&self, tick: f64) -> u32 {
+        let meter = self.meter_at(tick);
+        meter.bar + ((tick.max(0.0) - meter.start) / meter.ticks_per_bar()).floor() as u32
+    }
+
+    /// Returns the tick at which the bar with the given index, counting from 0, starts.
+    pub fn measure_start(&self, index: u32) -> f64 {
+        let i = self.meters.partition_point(|meter| meter.bar <= index) - 1;
+        let meter = &self.meters[i];
+        meter.start + (index - meter.bar) as f64 * meter.ticks_per_bar()
+    }
+
+    /// Returns the number of beats in the bar with the given index, counting from 0.
+    pub fn beats_in_measure(&self, index: u32) -> u8 {
+        self.meter_at(self.measure_start(index)).numerator
+    }
+
+    /// Returns the ticks at which the bars starting before `end` start.
+    pub fn measure_ticks(&self, end: f64) -> Vec<f64> {
+        (0..).map(|index| self.measure_start(index)).take_while(|tick| *tick < end).collect()
+    }
+
+    /// Returns the ticks at which the beats starting before `end` start,
+    /// leaving out the beats cut off by a change of time signature.
+    pub fn beat_ticks(&self, end: f64) -> Vec<f64> {
+        let mut beats = vec![];
+        for (index, start) in self.measure_ticks(end).into_iter().enumerate() {
+            let meter = self.meter_at(start);
+            let bar_end = self.measure_start(index as u32 + 1).min(end);
+            beats.extend(
+                (0..meter.numerator)
+                    .map(|beat| start + beat as f64 * meter.ticks_per_beat)
+                    .take_while(|tick| *tick < bar_end),
+            );
+        }
+        beats
+    }
+
+    pub fn tick_to_bbt(&self, tick: f64) -> BarBeatTick {
+        let tick = tick.max(0.0);
+        let index = self.measure_index(tick);
+        let meter = self.meter_at(tick);
+        let offset = tick - self.measure_start(index);
+        let beat = (offset / meter.ticks_per_beat).floor();
+
+        BarBeatTick {
+            bar: index + 1,
+            beat: beat as u32 + 1,
+            tick: offset - beat * meter.ticks_per_beat,
+        }
+    }
+
+    pub fn bbt_to_tick(&self, position: BarBeatTick) -> f64 {
+        let start = self.measure_start(position.bar.max(1) - 1);
+        let meter = self.meter_at(start);
+        start + (position.beat.max(1) - 1) as f64 * meter.ticks_per_beat + position.tick
+    }
+}
@@ -0,0 +1,86 @@
+use super::{ControlNo, ControlValue, Instrument, Pitch, RealTime};
+use std::collections::HashMap;
+
+pub const SUSTAIN_PEDAL: ControlNo = 64;
+pub const SOSTENUTO_PEDAL: ControlNo = 66;
+pub const SOFT_PEDAL: ControlNo = 67;
+
+/// The stretch of time during which a pedal is held down.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PedalInterval {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl PedalInterval {
+    fn holds(&self, time: f64) -> bool {
+        self.start <= time && time < self.end
+    }
+}
+
+impl Instrument<RealTime> {
+    /// Returns the intervals during which the given pedal is held down,
+    /// that is while its value is at least `threshold`.
+    /// A pedal that is never released is held until the last event of the instrument.
+    pub fn pedal_intervals(&self, number: ControlNo, threshold: ControlValue) -> Vec<PedalInterval> {
+        let mut intervals = vec![];
+        let mut pressed = None;
+
+        for cc in self.control_changes.iter().filter(|cc| cc.number == number) {
+            match (pressed, cc.value >= threshold) {
+                (None, true) => pressed = Some(cc.time),
+                (Some(start), false) => {
+                    intervals.push(PedalInterval { start, end: cc.time });
+                    pressed = None;
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(start) = pressed {
+            intervals.push(PedalInterval { start, end: self.end_time().max(start) });
+        }
+
+        intervals
+    }
+
+    /// Extends the notes released while the sustain or sostenuto pedal is held.
+    ///
+    /// A note released while the sustain pedal is down keeps sounding until the pedal is lifted.
+    /// The sostenuto pedal only sustains the notes already sounding when it is pressed.
+    /// A sustained note is cut short when the same pitch is played again.
+    pub fn apply_sustain_pedal(&mut self, threshold: ControlValue) {
+        let sustain = self.pedal_intervals(SUSTAIN_PEDAL, threshold);
+        let sostenuto = self.pedal_intervals(SOSTENUTO_PEDAL, threshold);
+
+        let mut released: Vec<_> = self.notes.iter().map(|note| note.end_time).collect();
+
+        for (note, end) in self.notes.iter_mut().zip(&mut released) {
+            if let Some(pedal) = sustain.iter().find(|pedal| pedal.holds(note.end_time)) {
+                note.end_time = note.end_time.max(pedal.end);
+            }
+
+            let held = sostenuto
+                .iter()
+                .filter(|pedal| note.start_time <= pedal.start && pedal.holds(*end));
+            for pedal in held {
+                note.end_time = note.end_time.max(pedal.end);
+            }
+        }
+
+        // Cut sustained notes short when their pitch is played again
+        let mut next_start: HashMap<Pitch, f64> = HashMap::new();
+        let mut order: Vec<_> = (0..self.notes.len()).collect();
+        order.sort_by(|a, b| self.notes[*b].start_time.total_cmp(&self.notes[*a].start_time));
+
+        for i in order {
+            let note = &mut self.notes[i];
+            if let Some(next) = next_start.get(&note.pitch) {
+                if note.end_time > *next {
+                    note.end_time = next.max(released[i]);
+                }
+            }
+            next_start.insert(note.pitch, note.start_time);
+        }
+    }
+}
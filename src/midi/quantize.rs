@@ -0,0 +1,96 @@
+use super::{Instrument, RealTime, TickConverter};
+
+/// Evenly spaced positions, meassured in beats, that notes are snapped onto.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Grid {
+    step: f64,
+    swing: f64,
+}
+
+impl Grid {
+    /// Creates a grid with `step` beats between its positions.
+    /// Every second position is delayed by `swing` times the step,
+    /// so a swing of a third gives a triplet feel.
+    pub fn new(step: f64, swing: f64) -> Self {
+        assert!(step > 0.0, "The step of a grid has to be positive.");
+        assert!((0.0..1.0).contains(&swing), "The swing of a grid has to be in [0, 1).");
+
+        Grid { step, swing }
+    }
+
+    /// The positions of the pair of steps starting at or before `beat`.
+    fn pair(&self, beat: f64) -> [f64; 3] {
+        let start = (beat / (2.0 * self.step)).floor() * 2.0 * self.step;
+        [start, start + (1.0 + self.swing) * self.step, start + 2.0 * self.step]
+    }
+
+    /// Returns the position closest to `beat`.
+    pub fn snap(&self, beat: f64) -> f64 {
+        self.pair(beat)
+            .into_iter()
+            .min_by(|a, b| (a - beat).abs().total_cmp(&(b - beat).abs()))
+            .unwrap()
+    }
+
+    /// Returns the first position after `beat`.
+    pub fn next(&self, beat: f64) -> f64 {
+        // Positions within rounding errors of `beat` are not after it
+        let after = beat + self.step * 1e-9;
+        self.pair(beat)
+            .into_iter()
+            .chain(self.pair(beat + 2.0 * self.step))
+            .find(|position| *position > after)
+            .unwrap()
+    }
+}
+
+/// Converts a note value like `"1/16"`, `"1/8t"` for triplets or `"1/4d"` for dotted notes
+/// into its length in beats, that is quarter notes.
+pub fn note_value_in_beats(name: &str) -> Option<f64> {
+    let name = name.trim();
+    let (fraction, factor) = match name.char_indices().last()? {
+        (i, 't' | 'T') => (&name[..i], 2.0 / 3.0),
+        (i, 'd' | 'D') => (&name[..i], 1.5),
+        _ => (name, 1.0),
+    };
+
+    let length = match fraction.split_once('/') {
+        Some((numer, denom)) => numer.trim().parse::<f64>().ok()? / denom.trim().parse::<f64>().ok()?,
+        None => fraction.parse().ok()?,
+    };
+
+    let beats = length * 4.0 * factor;
+    (beats.is_finite() && beats > 0.0).then_some(beats)
+}
+
+impl Instrument<RealTime> {
+    /// Moves the notes towards the closest position of the grid, which follows the tempo map.
+    /// A `strength` below one only moves the notes part of the way.
+    ///
+    /// With `quantize_ends` the ends of the notes are snapped as well,
+    /// extending notes that would vanish to the next position,
+    /// otherwise the notes keep their duration.
+    pub fn quantize(&mut self, grid: &Grid, converter: &TickConverter, strength: f64, quantize_ends: bool) {
+        let towards = |beat: f64, target: f64| converter.beat_to_time(beat + strength * (target - beat));
+
+        for note in &mut self.notes {
+            let start = converter.time_to_beat(note.start_time);
+            let end = converter.time_to_beat(note.end_time);
+            let snapped_start = grid.snap(start);
+
+            let start_time = towards(start, snapped_start);
+            note.end_time = if quantize_ends {
+                let mut snapped_end = grid.snap(end);
+                if snapped_end <= snapped_start {
+                    snapped_end = grid.next(snapped_start);
+                }
+                towards(end, snapped_end)
+            } else {
+                note.end_time + start_time - note.start_time
+            };
+            note.start_time = start_time;
+        }
+
+        self.notes.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+    }
+}
@@ -0,0 +1,53 @@
+use super::{Instrument, Note, Pitch, ProgramNo, RealTime, Velocity};
+
+/// Turns a piano roll, giving the velocity of each of the 128 pitches in frames of `1 / fs` seconds,
+/// into an instrument. A note starts where a pitch becomes active and lasts until it falls silent,
+/// taking the velocity of its first frame. When `onsets` are given, a note also starts on every
+/// active frame marked with a nonzero onset, ending the note sounding before it.
+pub fn piano_roll_to_instrument(
+    roll: &[Vec<f64>],
+    fs: f64,
+    program: ProgramNo,
+    onsets: Option<&[Vec<f64>]>,
+) -> Instrument<RealTime> {
+    assert!(fs > 0.0, "The sampling frequency of a piano roll has to be positive.");
+
+    let time = |frame: usize| frame as f64 / fs;
+    let mut notes: Vec<Note<RealTime>> = vec![];
+    for (pitch, frames) in roll.iter().enumerate() {
+        let onsets = onsets.and_then(|onsets| onsets.get(pitch));
+        let mut sounding: Option<(usize, Velocity)> = None;
+
+        for (frame, &value) in frames.iter().enumerate() {
+            let active = value > 0.0;
+            let onset = onsets.and_then(|onsets| onsets.get(frame)).is_some_and(|onset| *onset != 0.0);
+            if let Some((start, velocity)) = sounding {
+                if active && !onset {
+                    continue;
+                }
+                notes.push(Note {
+                    pitch: pitch as Pitch,
+                    velocity,
+                    start_time: time(start),
+                    end_time: time(frame),
+                });
+                sounding = None;
+            }
+            if active {
+                sounding = Some((frame, value.round().clamp(1.0, 127.0) as Velocity));
+            }
+        }
+
+        if let Some((start, velocity)) = sounding {
+            notes.push(Note {
+                pitch: pitch as Pitch,
+                velocity,
+                start_time: time(start),
+                end_time: time(frames.len()),
+            });
+        }
+    }
+
+    notes.sort_by(|a, b| a.start_time.total_cmp(&b.start_time).then(a.pitch.cmp(&b.pitch)));
+    Instrument { notes, ..Instrument::new(program, false) }
+}
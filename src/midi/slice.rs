@@ -0,0 +1,108 @@
+use super::{ControlChange, ControlNo, Instrument, Metadata, MidiTime, RealTime, TempoChange, TickConverter};
+use std::collections::BTreeMap;
+
+/// Returns the events within `[start, end)`, moved so that `start` becomes zero.
+/// With `carry_in` the last event before `start` is moved to zero as well,
+/// unless another event takes its place at `start`, so the state it sets is kept.
+pub fn slice_event_times<E: Clone>(
+    events: &[E],
+    time: impl Fn(&mut E) -> &mut f64,
+    start: f64,
+    end: f64,
+    carry_in: bool,
+) -> Vec<E> {
+    let mut timed: Vec<(f64, E)> = events.iter().cloned().map(|mut event| (*time(&mut event), event)).collect();
+    timed.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+    let first = timed.partition_point(|(t, _)| *t < start);
+    let last = timed.partition_point(|(t, _)| *t < end);
+    let carried = match timed.get(first) {
+        Some((t, _)) if *t == start => 0,
+        _ if carry_in => first.min(1),
+        _ => 0,
+    };
+
+    timed
+        .drain(first - carried..last)
+        .map(|(t, mut event)| {
+            *time(&mut event) = (t - start).max(0.0);
+            event
+        })
+        .collect()
+}
+
+impl Instrument<RealTime> {
+    /// Returns the part of the instrument between `start` and `end`, moved so that `start` becomes zero.
+    ///
+    /// With `clip_notes` the notes sounding at the edges are shortened to fit,
+    /// otherwise only the notes lying entirely within the range are kept.
+    /// The pitch bend and the value of each controller at `start` are carried in at zero.
+    pub fn slice(&self, start: f64, end: f64, clip_notes: bool) -> Self {
+        let notes = self
+            .notes
+            .iter()
+            .filter(|note| {
+                if clip_notes {
+                    note.start_time < end && note.end_time > start
+                } else {
+                    note.start_time >= start && note.end_time <= end
+                }
+            })
+            .map(|note| super::Note {
+                start_time: note.start_time.max(start) - start,
+                end_time: note.end_time.min(end) - start,
+                ..*note
+            })
+            .collect();
+
+        let mut controllers: BTreeMap<ControlNo, Vec<ControlChange<RealTime>>> = BTreeMap::new();
+        for cc in &self.control_changes {
+            controllers.entry(cc.number).or_default().push(cc.clone());
+        }
+        let mut control_changes: Vec<_> = controllers
+            .values()
+            .flat_map(|ccs| slice_event_times(ccs, |cc| &mut cc.time, start, end, true))
+            .collect();
+        control_changes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        Instrument {
+            name: self.name.clone(),
+            notes,
+            pitch_bends: slice_event_times(&self.pitch_bends, |bend| &mut bend.time, start, end, true),
+            control_changes,
+            ..Instrument::<RealTime>::new(self.program, self.is_drum)
+        }
+    }
+}
+
+impl Metadata<RealTime> {
+    /// Returns the metadata between `start` and `end`, moved so that `start` becomes zero.
+    /// The key and time signatures in effect at `start` are carried in at zero.
+    pub fn slice(&self, start: f64, end: f64) -> Self {
+        Metadata {
+            key_signature_changes: slice_event_times(&self.key_signature_changes, |ks| &mut ks.time, start, end, true),
+            time_signature_changes: slice_event_times(&self.time_signature_changes, |ts| &mut ts.time, start, end, true),
+            lyrics: slice_event_times(&self.lyrics, |lyric| &mut lyric.time, start, end, false),
+            text_events: slice_event_times(&self.text_events, |text| &mut text.time, start, end, false),
+        }
+    }
+}
+
+impl TickConverter {
+    /// Returns the tempo map starting at `start`, with the tempo in effect there at tick 0.
+    pub fn slice(&self, start: f64) -> TickConverter {
+        let start_tick = self.time_to_beat(start) * self.resolution() as f64;
+        let i = self.tempo_map.partition_point(|(tick, _)| *tick as f64 <= start_tick).max(1) - 1;
+
+        let mut tempo_map: Vec<TempoChange> = vec![(0, self.tempo_map[i].1)];
+        for (tick, tempo) in &self.tempo_map[i + 1..] {
+            let tick = (*tick as f64 - start_tick).round() as MidiTime;
+            match tempo_map.last_mut() {
+                Some(last) if last.0 == tick => *last = (tick, *tempo),
+                _ => tempo_map.push((tick, *tempo)),
+            }
+        }
+
+        TickConverter::new(&tempo_map, self.resolution())
+    }
+}
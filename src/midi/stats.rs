@@ -0,0 +1,175 @@
+use super::{Instrument, Pitch, ProgramNo, RealTime, Velocity};
+
+const INSTRUMENT_CLASSES: [&str; 16] = [
+    "Piano",
+    "Chromatic Percussion",
+    "Organ",
+    "Guitar",
+    "Bass",
+    "Strings",
+    "Ensemble",
+    "Brass",
+    "Reed",
+    "Pipe",
+    "Synth Lead",
+    "Synth Pad",
+    "Synth Effects",
+    "Ethnic",
+    "Percussive",
+    "Sound Effects",
+];
+
+/// The number of velocities counted together in the velocity histogram.
+pub const VELOCITY_BIN_SIZE: usize = 16;
+
+/// Returns the General MIDI family of a program like `pretty_midi.program_to_instrument_class`.
+pub fn program_to_instrument_class(program: ProgramNo) -> &'static str {
+    INSTRUMENT_CLASSES[program as usize / 8 % 16]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstrumentStats {
+    pub program: ProgramNo,
+    pub is_drum: bool,
+    pub name: String,
+    /// The General MIDI family of the program, or `"Drums"`.
+    pub family: &'static str,
+    pub note_count: usize,
+}
+
+/// A summary of the notes of a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub duration: f64,
+    pub note_count: usize,
+    /// The largest number of notes sounding at once, not counting drums.
+    pub max_polyphony: usize,
+    /// The average number of notes sounding at once while any is sounding, not counting drums.
+    pub mean_polyphony: f64,
+    /// The lowest and highest pitch, not counting drums.
+    pub pitch_range: Option<(Pitch, Pitch)>,
+    pub velocity_mean: f64,
+    pub velocity_std: f64,
+    pub velocity_range: Option<(Velocity, Velocity)>,
+    /// The number of notes in each range of [`VELOCITY_BIN_SIZE`] velocities.
+    pub velocity_histogram: Vec<usize>,
+    /// The number of notes starting per second within each window of the file.
+    pub note_density: Vec<f64>,
+    /// The number of tempos in the tempo map, including the initial one.
+    pub tempo_changes: usize,
+    pub instruments: Vec<InstrumentStats>,
+    /// The fraction of the notes played by drum instruments.
+    pub drum_ratio: f64,
+}
+
+impl Stats {
+    /// Summarizes the instruments of a file lasting `duration` seconds,
+    /// measuring the note density in windows of `window` seconds.
+    pub fn new(instruments: &[Instrument<RealTime>], duration: f64, tempo_changes: usize, window: f64) -> Self {
+        assert!(window > 0.0, "The window of the note density has to be positive.");
+
+        let notes = || instruments.iter().flat_map(|instrument| &instrument.notes);
+        let pitched = || {
+            instruments
+                .iter()
+                .filter(|instrument| !instrument.is_drum)
+                .flat_map(|instrument| &instrument.notes)
+        };
+
+        let note_count = notes().count();
+        let drum_count = note_count - pitched().count();
+
+        let (max_polyphony, mean_polyphony) = polyphony(pitched().map(|note| (note.start_time, note.end_time)));
+
+        let pitch_range = pitched()
+            .map(|note| note.pitch)
+            .fold(None, |range, pitch| match range {
+                None => Some((pitch, pitch)),
+                Some((low, high)) => Some((pitch.min(low), pitch.max(high))),
+            });
+        let velocity_range = notes()
+            .map(|note| note.velocity)
+            .fold(None, |range, velocity| match range {
+                None => Some((velocity, velocity)),
+                Some((low, high)) => Some((velocity.min(low), velocity.max(high))),
+            });
+
+        let (velocity_mean, velocity_std) = if note_count > 0 {
+            let mean = notes().map(|note| note.velocity as f64).sum::<f64>() / note_count as f64;
+            let variance = notes().map(|note| (note.velocity as f64 - mean).powi(2)).sum::<f64>() / note_count as f64;
+            (mean, variance.sqrt())
+        } else {
+            (0.0, 0.0)
+        };
+
+        let mut velocity_histogram = vec![0; 128 / VELOCITY_BIN_SIZE];
+        for note in notes() {
+            velocity_histogram[note.velocity as usize / VELOCITY_BIN_SIZE] += 1;
+        }
+
+        // Notes starting at the very end count towards the last window
+        let windows = (duration / window).ceil() as usize;
+        let mut note_density = vec![0.0; windows];
+        for note in notes() {
+            let i = ((note.start_time / window) as usize).min(windows.saturating_sub(1));
+            if let Some(count) = note_density.get_mut(i) {
+                *count += 1.0 / window;
+            }
+        }
+
+        Stats {
+            duration,
+            note_count,
+            max_polyphony,
+            mean_polyphony,
+            pitch_range,
+            velocity_mean,
+            velocity_std,
+            velocity_range,
+            velocity_histogram,
+            note_density,
+            tempo_changes,
+            instruments: instruments
+                .iter()
+                .map(|instrument| InstrumentStats {
+                    program: instrument.program,
+                    is_drum: instrument.is_drum,
+                    name: instrument.name.clone(),
+                    family: if instrument.is_drum {
+                        "Drums"
+                    } else {
+                        program_to_instrument_class(instrument.program)
+                    },
+                    note_count: instrument.notes.len(),
+                })
+                .collect(),
+            drum_ratio: if note_count > 0 { drum_count as f64 / note_count as f64 } else { 0.0 },
+        }
+    }
+}
+
+/// Returns the largest number of overlapping intervals and
+/// the average number of them during the time covered by any.
+fn polyphony(intervals: impl Iterator<Item = (f64, f64)>) -> (usize, f64) {
+    // Notes ending at the same time as others start do not overlap
+    let mut events: Vec<(f64, i32)> = intervals
+        .filter(|(start, end)| end > start)
+        .flat_map(|(start, end)| [(start, 1), (end, -1)])
+        .collect();
+    events.sort_by(|(a, a_change), (b, b_change)| a.total_cmp(b).then(a_change.cmp(b_change)));
+
+    let (mut sounding, mut max) = (0, 0);
+    let (mut weighted, mut covered) = (0.0, 0.0);
+    let mut last_time = 0.0;
+    for (time, change) in events {
+        if sounding > 0 {
+            weighted += sounding as f64 * (time - last_time);
+            covered += time - last_time;
+        }
+        sounding += change;
+        max = max.max(sounding);
+        last_time = time;
+    }
+
+    (max as usize, if covered > 0.0 { weighted / covered } else { 0.0 })
+}
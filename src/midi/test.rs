@@ -1,27 +1,29 @@
 use std::error::Error;
 
+use midly::{num::u28, Header, Format, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind};
+
 use crate::midi::MidiReader;
 
-const NOTE_DATA: &'static [u8] = include_bytes!("../../test_data/notes.json");
-const SCALE_DATA: &'static [u8] = include_bytes!("../../test_data/scales.json");
-const MIDI_DATA: &'static [u8] = include_bytes!("../../test_data/source.mid");
+const NOTE_DATA: &[u8] = include_bytes!("../../test_data/notes.json");
+const SCALE_DATA: &[u8] = include_bytes!("../../test_data/scales.json");
+const MIDI_DATA: &[u8] = include_bytes!("../../test_data/source.mid");
 
-const TOLERANCE: f32 = 0.000001;
+const TOLERANCE: f64 = 1e-9;
 
-fn compare_f32(a: f32, b: f32) {
-    assert!(a == b || a.abs_sub(b) < TOLERANCE)
+fn compare_f64(a: f64, b: f64) {
+    assert!(a == b || (a - b).abs() < TOLERANCE)
 }
 
 type TestResult = Result<(), Box<dyn Error>>;
 
 #[test]
 fn test_instrument_scanning() -> TestResult {
-    let source_data: Vec<super::Note<super::RealTime>> = serde_json::from_slice(NOTE_DATA)?;
+    let mut source_data: Vec<super::Note<super::RealTime>> = serde_json::from_slice(NOTE_DATA)?;
+    // pretty-midi stores notes in the order they end, we order them by their start
+    source_data.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
     let mut smf = midly::Smf::parse(MIDI_DATA)?;
-    
-    smf.tracks = smf.tracks.into_iter().map(super::make_track_time_absolute).collect();
 
-    let mut reader = MidiReader::new(&smf);
+    let mut reader = MidiReader::new(&mut smf)?;
     let instruments = reader.build_instrument_data();
 
     assert_eq!(instruments.len(), 1);
@@ -35,8 +37,8 @@ fn test_instrument_scanning() -> TestResult {
         .for_each(|(reference, generated)| {
             assert_eq!(reference.pitch, generated.pitch);
             assert_eq!(reference.velocity, generated.velocity);
-            compare_f32(reference.start_time, generated.start_time);
-            compare_f32(reference.end_time, generated.end_time);
+            compare_f64(reference.start_time, generated.start_time);
+            compare_f64(reference.end_time, generated.end_time);
         });
 
     Ok(())
@@ -46,9 +48,11 @@ fn test_instrument_scanning() -> TestResult {
 fn test_tick_scale_computation() -> TestResult {
     let reference_values: Vec<super::TickScale> = serde_json::from_slice(SCALE_DATA)?;
     let source_data = midly::Smf::parse(MIDI_DATA)?;
-    
-    let timing = super::get_timing(&source_data);
-    let generated_scales = super::generate_tick_scales(&source_data.tracks[0], timing);
+    let timing = super::get_timing(&source_data)?;
+    let tracks: Vec<_> = source_data.tracks.into_iter().map(super::make_track_time_absolute).collect();
+
+    let generated_scales = super::TickConverter::new(&super::generate_tempo_map(&tracks), timing);
+    let generated_scales = generated_scales.tick_scales();
     
     assert_eq!(generated_scales.len(), reference_values.len());
             
@@ -60,3 +64,862 @@ fn test_tick_scale_computation() -> TestResult {
             
     Ok(())
 }
+
+/// Builds a track from `(delta, channel, message)` triples.
+fn midi_track(events: &[(u32, u8, MidiMessage)]) -> Vec<TrackEvent<'static>> {
+    events.iter()
+        .map(|&(delta, channel, message)| TrackEvent {
+            delta: u28::new(delta),
+            kind: TrackEventKind::Midi { channel: channel.into(), message },
+        })
+        .chain(std::iter::once(TrackEvent {
+            delta: 0.into(),
+            kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+        }))
+        .collect()
+}
+
+fn tempo_track(tempo: u32) -> Vec<TrackEvent<'static>> {
+    vec![
+        TrackEvent { delta: 0.into(), kind: TrackEventKind::Meta(MetaMessage::Tempo(tempo.into())) },
+        TrackEvent { delta: 0.into(), kind: TrackEventKind::Meta(MetaMessage::EndOfTrack) },
+    ]
+}
+
+fn note_on(key: u8, vel: u8) -> MidiMessage {
+    MidiMessage::NoteOn { key: key.into(), vel: vel.into() }
+}
+
+fn note_off(key: u8) -> MidiMessage {
+    MidiMessage::NoteOff { key: key.into(), vel: 0.into() }
+}
+
+fn program_change(program: u8) -> MidiMessage {
+    MidiMessage::ProgramChange { program: program.into() }
+}
+
+fn note(pitch: u8, start_time: f64, end_time: f64) -> super::Note<super::RealTime> {
+    note_with_velocity(pitch, 100, start_time, end_time)
+}
+
+fn note_with_velocity(pitch: u8, velocity: u8, start_time: f64, end_time: f64) -> super::Note<super::RealTime> {
+    super::Note { pitch, velocity, start_time, end_time }
+}
+
+fn cc(number: u8, value: u8, time: f64) -> super::ControlChange<super::RealTime> {
+    super::ControlChange { number, value, time }
+}
+
+#[test]
+fn test_instrument_order() -> TestResult {
+    let mut smf = Smf::new(Header::new(Format::Parallel, Timing::Metrical(480.into())));
+    smf.tracks.push(tempo_track(500_000));
+    smf.tracks.push(midi_track(&[
+        (0, 3, program_change(40)),
+        (0, 3, note_on(60, 100)),
+        (0, 9, note_on(36, 100)),
+        (0, 0, note_on(64, 100)),
+        // Closed first, so the drum instrument comes first
+        (10, 9, note_off(36)),
+        (10, 0, note_on(62, 100)),
+        (10, 0, note_off(64)),
+        (10, 3, note_off(60)),
+        (0, 0, note_off(62)),
+    ]));
+    smf.tracks.push(midi_track(&[
+        (0, 1, program_change(1)),
+        (0, 1, note_on(60, 100)),
+        (10, 1, note_off(60)),
+    ]));
+
+    for _ in 0..8 {
+        let mut smf = smf.clone();
+        let mut reader = MidiReader::new(&mut smf)?;
+        let instruments = reader.build_instrument_data();
+
+        let order: Vec<_> = instruments.iter()
+            .map(|instrument| (instrument.program, instrument.is_drum))
+            .collect();
+        assert_eq!(order, [(0, true), (0, false), (40, false), (1, false)]);
+
+        let pitches: Vec<_> = instruments[1].notes.iter().map(|note| note.pitch).collect();
+        assert_eq!(pitches, [64, 62]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_unusual_files() -> TestResult {
+    // A single track file with its tempo, meter and notes on two channels in one track
+    let mut track = vec![
+        TrackEvent { delta: 0.into(), kind: TrackEventKind::Meta(MetaMessage::Tempo(250_000.into())) },
+        TrackEvent { delta: 0.into(), kind: TrackEventKind::Meta(MetaMessage::TimeSignature(3, 2, 24, 8)) },
+        // A denominator of 2^8 does not fit and is skipped
+        TrackEvent { delta: 0.into(), kind: TrackEventKind::Meta(MetaMessage::TimeSignature(3, 8, 24, 8)) },
+        // As is a numerator of 0, which would make bars without any length
+        TrackEvent { delta: 0.into(), kind: TrackEventKind::Meta(MetaMessage::TimeSignature(0, 2, 24, 8)) },
+    ];
+    track.extend(midi_track(&[
+        (0, 0, program_change(40)),
+        (0, 0, note_on(60, 100)),
+        (0, 9, note_on(36, 100)),
+        (480, 0, note_off(60)),
+        (0, 9, note_off(36)),
+    ]));
+    let mut smf = Smf::new(Header::new(Format::SingleTrack, Timing::Metrical(480.into())));
+    smf.tracks.push(track);
+
+    let mut reader = MidiReader::new(&mut smf)?;
+    let instruments = reader.build_instrument_data();
+    let order: Vec<_> = instruments.iter()
+        .map(|instrument| (instrument.program, instrument.is_drum, instrument.notes.len()))
+        .collect();
+    assert_eq!(order, [(40, false, 1), (0, true, 1)]);
+    compare_f64(instruments[0].notes[0].end_time, 0.25);
+
+    let metadata = reader.build_tick_metadata();
+    assert_eq!(metadata.time_signature_changes.len(), 1);
+    assert_eq!(metadata.time_signature_changes[0].denominator, 4);
+
+    let mut smf = Smf::new(Header::new(Format::Parallel, Timing::Timecode(midly::Fps::Fps25, 40)));
+    smf.tracks.push(tempo_track(500_000));
+    assert!(MidiReader::new(&mut smf).is_err());
+
+    let mut smf = Smf::new(Header::new(Format::Sequential, Timing::Metrical(480.into())));
+    smf.tracks.push(tempo_track(500_000));
+    assert!(MidiReader::new(&mut smf).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_note_pairing() -> TestResult {
+    use super::NotePairing;
+
+    // A tempo of 125 bpm at 480 ticks per beat makes every tick last a millisecond
+    let mut smf = Smf::new(Header::new(Format::Parallel, Timing::Metrical(480.into())));
+    smf.tracks.push(tempo_track(480_000));
+    smf.tracks.push(midi_track(&[
+        (0, 0, note_on(60, 100)),
+        (10, 0, note_on(60, 90)),
+        (10, 0, note_off(60)),
+        (10, 0, note_off(60)),
+        (10, 0, note_on(62, 80)),
+        (0, 0, note_off(62)),
+        (10, 0, note_on(64, 70)),
+    ]));
+
+    let cases = [
+        (NotePairing::PrettyMidi, vec![(60, 0, 20), (60, 10, 20)]),
+        (NotePairing::Fifo, vec![(60, 0, 20), (60, 10, 30), (62, 40, 40)]),
+        (NotePairing::Lifo, vec![(60, 0, 30), (60, 10, 20), (62, 40, 40)]),
+        (NotePairing::CloseAll, vec![(60, 0, 20), (60, 10, 20), (62, 40, 40)]),
+    ];
+
+    for (pairing, expected) in cases {
+        let mut smf = smf.clone();
+        let mut reader = MidiReader::new(&mut smf)?.with_note_pairing(pairing);
+
+        let notes: Vec<_> = reader.build_instrument_data()[0].notes.iter()
+            .map(|note| (
+                note.pitch,
+                (note.start_time * 1000.0).round() as u32,
+                (note.end_time * 1000.0).round() as u32,
+            ))
+            .collect();
+        assert_eq!(notes, expected, "{:?}", pairing);
+
+        let dangling = reader.dangling_notes();
+        assert_eq!(dangling.len(), 1);
+        assert_eq!((dangling[0].track, dangling[0].pitch, dangling[0].velocity), (1, 64, 70));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_resolution_and_track_tempo() -> TestResult {
+    // 96 ticks per beat with a tempo change halfway through a note, given on a non-conductor track
+    let mut smf = Smf::new(Header::new(Format::Parallel, Timing::Metrical(96.into())));
+    smf.tracks.push(tempo_track(500_000));
+    let mut track = midi_track(&[
+        (0, 0, note_on(60, 100)),
+        (192, 0, note_off(60)),
+    ]);
+    track.insert(1, TrackEvent {
+        delta: 96.into(),
+        kind: TrackEventKind::Meta(MetaMessage::Tempo(1_000_000.into())),
+    });
+    track[2].delta = 96.into();
+    smf.tracks.push(track);
+
+    let mut reader = MidiReader::new(&mut smf)?;
+    assert_eq!(reader.resolution(), 96);
+
+    let instruments = reader.build_instrument_data();
+    let note = &instruments[0].notes[0];
+    compare_f64(note.start_time, 0.0);
+    compare_f64(note.end_time, 1.5);
+
+    let tempi: Vec<_> = reader.tick_converter().tempo_changes();
+    assert_eq!(tempi, [(0.0, 120.0), (0.5, 60.0)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_time_round_trip() -> TestResult {
+    let mut smf = midly::Smf::parse(MIDI_DATA)?;
+    smf.tracks[0].insert(1, TrackEvent {
+        delta: 1000.into(),
+        kind: TrackEventKind::Meta(MetaMessage::Tempo(433_337.into())),
+    });
+
+    let mut reader = MidiReader::new(&mut smf)?;
+    let instruments = reader.build_tick_instrument_data();
+    let converter = reader.tick_converter();
+
+    for note in &instruments[0].notes {
+        for tick in [note.start_time, note.end_time] {
+            assert_eq!(converter.exact_to_tick(converter.tick_to_exact(tick)), tick);
+            assert_eq!(converter.time_to_tick(converter.tick_to_time(tick)), tick);
+        }
+    }
+
+    let real = instruments[0].clone().into_real_time(converter);
+    assert_eq!(real.into_tick_time(converter).notes, instruments[0].notes);
+
+    Ok(())
+}
+
+#[test]
+fn test_beat_time() -> TestResult {
+    let mut smf = midly::Smf::parse(MIDI_DATA)?;
+    smf.tracks[0].insert(1, TrackEvent {
+        delta: 5000.into(),
+        kind: TrackEventKind::Meta(MetaMessage::Tempo(300_000.into())),
+    });
+
+    let mut reader = MidiReader::new(&mut smf)?;
+    let instrument = reader.build_tick_instrument_data().remove(0);
+    let converter = reader.tick_converter();
+
+    let from_ticks = instrument.clone().into_beat_time(converter);
+    let real = instrument.clone().map_time::<super::RealTime>(|tick| converter.tick_to_time(tick));
+    let from_real = real.clone().into_beat_time(converter);
+    let back_to_real = from_real.clone().into_real_time(converter);
+
+    for (tick, (a, b)) in instrument.notes.iter().zip(from_ticks.notes.iter().zip(&from_real.notes)) {
+        assert_eq!(a.start_time, tick.start_time as f64 / 480.0);
+        compare_f64(a.start_time, b.start_time);
+        compare_f64(a.end_time, b.end_time);
+    }
+
+    for (a, b) in real.notes.iter().zip(&back_to_real.notes) {
+        compare_f64(a.start_time, b.start_time);
+        compare_f64(a.end_time, b.end_time);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_bar_beat_tick() {
+    use super::{BarBeatTick, MeterConverter, TimeSignature};
+
+    let meter = MeterConverter::new(&[
+        TimeSignature { numerator: 4, denominator: 4, time: 0 },
+        // Two bars of 4/4 followed by 3/4
+        TimeSignature { numerator: 3, denominator: 4, time: 3840 },
+        // Half way into the second bar of 3/4, which cuts that bar short
+        TimeSignature { numerator: 6, denominator: 8, time: 5520 },
+    ], 480);
+
+    let bbt = |bar, beat, tick| BarBeatTick { bar, beat, tick };
+
+    assert_eq!(meter.tick_to_bbt(0.0), bbt(1, 1, 0.0));
+    assert_eq!(meter.tick_to_bbt(2000.0), bbt(2, 1, 80.0));
+    assert_eq!(meter.tick_to_bbt(3840.0 + 1440.0), bbt(4, 1, 0.0));
+    assert_eq!(meter.tick_to_bbt(5520.0), bbt(5, 1, 0.0));
+    assert_eq!(meter.tick_to_bbt(5520.0 + 1440.0 + 250.0), bbt(6, 2, 10.0));
+
+    assert_eq!(meter.measure_index(5519.0), 3);
+    assert_eq!(meter.measure_index(5520.0), 4);
+    assert_eq!(meter.beats_in_measure(4), 6);
+
+    for tick in [0.0, 479.5, 3840.0, 5000.0, 5520.0, 9000.0] {
+        assert_eq!(meter.bbt_to_tick(meter.tick_to_bbt(tick)), tick);
+    }
+}
+
+#[test]
+fn test_sustain_pedal() {
+    use super::{Instrument, RealTime};
+
+    let mut instrument = Instrument::<RealTime>::new(0, false);
+    instrument.notes = vec![
+        note(60, 0.0, 0.5),
+        // Released while the sustain pedal is down, cut short when played again
+        note(62, 1.0, 1.5),
+        note(62, 2.0, 2.5),
+        // Held by the sostenuto pedal, unlike the note started after it was pressed
+        note(64, 3.5, 4.5),
+        note(65, 4.2, 4.6),
+    ];
+    instrument.control_changes = vec![
+        cc(64, 100, 1.2),
+        cc(64, 0, 3.0),
+        cc(66, 127, 4.0),
+        cc(66, 0, 5.0),
+    ];
+
+    let intervals = instrument.pedal_intervals(64, 64);
+    assert_eq!(intervals, [super::PedalInterval { start: 1.2, end: 3.0 }]);
+
+    instrument.apply_sustain_pedal(64);
+    let ends: Vec<_> = instrument.notes.iter().map(|note| note.end_time).collect();
+    assert_eq!(ends, [0.5, 2.0, 3.0, 5.0, 4.6]);
+}
+
+#[test]
+fn test_clean() {
+    use super::{Instrument, RealTime};
+
+    let mut instrument = Instrument::<RealTime>::new(0, false);
+    instrument.notes = vec![
+        note_with_velocity(60, 80, 0.0, 1.0),
+        // Duplicate of the note above
+        note(60, 0.0, 0.5),
+        // Overlaps the next note of the same pitch
+        note(62, 0.5, 2.0),
+        note(62, 1.5, 2.5),
+        // Invalid and zero-length notes
+        note(64, 1.0, 0.5),
+        note(65, 1.0, 1.0),
+        // Past the end of the file
+        note(67, 2.0, 4.0),
+        note(69, 3.5, 4.0),
+    ];
+
+    let mut invalid_removed = instrument.clone();
+    invalid_removed.remove_invalid_notes();
+    assert_eq!(invalid_removed.notes.len(), 6);
+
+    instrument.clean(Some(3.0));
+    assert_eq!(
+        instrument.notes,
+        [
+            note(60, 0.0, 1.0),
+            note(62, 0.5, 1.5),
+            note(62, 1.5, 2.5),
+            note(67, 2.0, 3.0),
+        ]
+    );
+}
+
+#[test]
+fn test_adjust_times() {
+    use super::{Instrument, RealTime, TickConverter, TimeWarp};
+
+    // The tempo doubles at tick 400, two seconds in
+    let converter = TickConverter::new(&[(0, 500_000), (400, 250_000)], 100);
+
+    let mut instrument = Instrument::<RealTime>::new(0, false);
+    instrument.notes = vec![note(60, 0.5, 1.5), note(60, 1.0, 2.0), note(60, 2.0, 3.0), note(60, 2.5, 3.5)];
+    instrument.control_changes = vec![cc(7, 0, 0.0), cc(7, 1, 0.5), cc(7, 2, 2.0), cc(7, 3, 3.0)];
+
+    // Play the section between one and three seconds twice as fast, starting half a second in
+    let warp = TimeWarp::new(vec![1.0, 3.0], vec![0.5, 1.5]);
+    instrument.adjust_times(&warp);
+    assert_eq!(instrument.notes, [note(60, 0.5, 1.0), note(60, 1.0, 1.5)]);
+    assert_eq!(instrument.control_changes, [cc(7, 1, 0.5), cc(7, 2, 1.0)]);
+
+    let adjusted = converter.adjust_times(&warp);
+    assert_eq!(adjusted.tempo_map, [(0, 500_000), (100, 250_000), (300, 125_000), (700, 250_000)]);
+    // The ticks of the original times and the tempo change land on the new times
+    for (tick, time) in [(100, 0.5), (300, 1.0), (700, 1.5)] {
+        compare_f64(adjusted.tick_to_time(tick), time);
+    }
+}
+
+#[test]
+fn test_slice() {
+    use super::{Instrument, PitchBend, RealTime, TickConverter};
+
+    let mut instrument = Instrument::<RealTime>::new(0, false);
+    instrument.notes = vec![note(60, 0.0, 1.5), note(62, 1.5, 2.5), note(64, 2.5, 3.5), note(65, 3.0, 3.5)];
+    instrument.pitch_bends = vec![PitchBend { bend: 9000, time: 0.5 }];
+    instrument.control_changes = vec![cc(7, 90, 0.0), cc(7, 80, 0.5), cc(64, 127, 0.5), cc(64, 0, 1.0), cc(7, 70, 2.0)];
+
+    let sliced = instrument.slice(1.0, 3.0, true);
+    assert_eq!(sliced.notes, [note(60, 0.0, 0.5), note(62, 0.5, 1.5), note(64, 1.5, 2.0)]);
+    // The state of the controllers at the start is carried in
+    assert_eq!(sliced.pitch_bends, [PitchBend { bend: 9000, time: 0.0 }]);
+    assert_eq!(sliced.control_changes, [cc(7, 80, 0.0), cc(64, 0, 0.0), cc(7, 70, 1.0)]);
+
+    let filtered = instrument.slice(1.0, 3.0, false);
+    assert_eq!(filtered.notes, [note(62, 0.5, 1.5)]);
+
+    // The tempo doubles at tick 400, two seconds in
+    let converter = TickConverter::new(&[(0, 500_000), (400, 250_000)], 100);
+    let sliced = converter.slice(1.0);
+    assert_eq!(sliced.tempo_map, [(0, 500_000), (200, 250_000)]);
+    assert_eq!(converter.slice(2.5).tempo_map, [(0, 250_000)]);
+}
+
+#[test]
+fn test_merge_and_concat() {
+    use super::{merge_instruments, Instrument, RealTime, TickConverter};
+
+    let instrument = |program, notes| Instrument::<RealTime> { notes, ..Instrument::new(program, false) };
+
+    let first = [instrument(0, vec![note(60, 0.0, 1.0), note(64, 1.0, 2.0)])];
+    let second = [instrument(0, vec![note(62, 0.5, 1.0)]), instrument(40, vec![note(72, 0.0, 0.5)])];
+
+    // Instruments of the same part are merged, the others are kept apart
+    let merged = merge_instruments(&first, &second, 0.0);
+    assert_eq!(merged.len(), 2);
+    assert_eq!(merged[0].notes, [note(60, 0.0, 1.0), note(62, 0.5, 1.0), note(64, 1.0, 2.0)]);
+    assert_eq!(merged[1].notes, [note(72, 0.0, 0.5)]);
+
+    let concatenated = merge_instruments(&first, &second, 2.0);
+    assert_eq!(concatenated[0].notes[2], note(62, 2.5, 3.0));
+    assert_eq!(concatenated[1].notes, [note(72, 2.0, 2.5)]);
+
+    // The second tempo map starts at the offset, rescaled to the first resolution
+    let first = TickConverter::new(&[(0, 500_000), (400, 250_000)], 100);
+    let second = TickConverter::new(&[(0, 600_000), (200, 500_000)], 200);
+    assert_eq!(
+        first.concat(&second, 300).tempo_map,
+        [(0, 500_000), (300, 600_000), (400, 500_000)]
+    );
+}
+
+#[test]
+fn test_transpose() {
+    use super::{key_name_to_key_number, Instrument, KeySignature, PitchOverflow, RealTime};
+
+    let pitches = |instrument: &Instrument<RealTime>| -> Vec<u8> {
+        instrument.notes.iter().map(|note| note.pitch).collect()
+    };
+
+    let mut instrument = Instrument::<RealTime>::new(0, false);
+    instrument.notes = vec![note(5, 0.0, 1.0), note(60, 1.0, 2.0), note(122, 2.0, 3.0)];
+
+    let mut dropped = instrument.clone();
+    dropped.transpose(-7, PitchOverflow::Drop);
+    assert_eq!(pitches(&dropped), [53, 115]);
+    let mut clamped = instrument.clone();
+    clamped.transpose(7, PitchOverflow::Clamp);
+    assert_eq!(pitches(&clamped), [12, 67, 127]);
+    let mut folded = instrument.clone();
+    folded.transpose(7, PitchOverflow::Fold);
+    assert_eq!(pitches(&folded), [12, 67, 117]);
+
+    let mut drums = Instrument::<RealTime>::new(0, true);
+    drums.notes = vec![note(36, 0.0, 1.0)];
+    drums.transpose(2, PitchOverflow::Drop);
+    assert_eq!(pitches(&drums), [36]);
+
+    // Up a third in C major, then in A minor from the third second on
+    let keys = [
+        KeySignature { key_number: 0, time: 0.0 },
+        KeySignature { key_number: key_name_to_key_number("A minor").unwrap(), time: 3.0 },
+    ];
+    let mut diatonic = Instrument::<RealTime>::new(0, false);
+    diatonic.notes = vec![
+        note(60, 0.0, 1.0), note(64, 1.0, 2.0), note(61, 2.0, 3.0), note(69, 3.0, 4.0), note(74, 4.0, 5.0),
+    ];
+    diatonic.transpose_diatonic(2, &keys, PitchOverflow::Drop);
+    assert_eq!(pitches(&diatonic), [64, 67, 65, 72, 77]);
+
+    assert_eq!(key_name_to_key_number("Bb minor"), Some(22));
+    assert_eq!(key_name_to_key_number("f#"), Some(18));
+    assert_eq!(key_name_to_key_number("Db Major"), Some(1));
+    assert_eq!(key_name_to_key_number("H major"), None);
+    assert_eq!(KeySignature { key_number: 21, time: 0.0 }.transpose(3).key_number, 12);
+}
+
+#[test]
+fn test_quantize() {
+    use super::{note_value_in_beats, Grid, Instrument, RealTime, TickConverter};
+
+    let times = |instrument: &Instrument<RealTime>| -> Vec<(f64, f64)> {
+        instrument.notes.iter().map(|note| (note.start_time, note.end_time)).collect()
+    };
+    let compare = |a: Vec<(f64, f64)>, b: &[(f64, f64)]| {
+        assert_eq!(a.len(), b.len());
+        for ((a_start, a_end), (b_start, b_end)) in a.iter().zip(b) {
+            compare_f64(*a_start, *b_start);
+            compare_f64(*a_end, *b_end);
+        }
+    };
+
+    assert_eq!(note_value_in_beats("1/16"), Some(0.25));
+    assert_eq!(note_value_in_beats("1/4d"), Some(1.5));
+    compare_f64(note_value_in_beats("1/8t").unwrap(), 1.0 / 3.0);
+    assert_eq!(note_value_in_beats("sixteenth"), None);
+
+    // Half a second per beat, so an eighth note grid is a quarter of a second
+    let converter = TickConverter::new(&[(0, 500_000)], 100);
+    let mut instrument = Instrument::<RealTime>::new(0, false);
+    instrument.notes = vec![note(60, 0.02, 0.2), note(60, 0.3, 0.32), note(60, 0.7, 1.1)];
+
+    let mut quantized = instrument.clone();
+    quantized.quantize(&Grid::new(0.5, 0.0), &converter, 1.0, true);
+    compare(times(&quantized), &[(0.0, 0.25), (0.25, 0.5), (0.75, 1.0)]);
+
+    let mut halfway = instrument.clone();
+    halfway.quantize(&Grid::new(0.5, 0.0), &converter, 0.5, false);
+    compare(times(&halfway), &[(0.01, 0.19), (0.275, 0.295), (0.725, 1.125)]);
+
+    // The off-beat eighths are delayed to the last eighth triplet
+    let mut swung = instrument.clone();
+    swung.quantize(&Grid::new(0.5, 1.0 / 3.0), &converter, 1.0, true);
+    compare(times(&swung), &[(0.0, 1.0 / 3.0), (1.0 / 3.0, 0.5), (5.0 / 6.0, 1.0)]);
+}
+
+#[test]
+fn test_pitch_class_features() {
+    use super::{Instrument, RealTime};
+
+    let mut instrument = Instrument::<RealTime>::new(0, false);
+    instrument.notes = vec![note(60, 0.0, 1.0), note_with_velocity(76, 50, 1.0, 2.0), note(55, 1.02, 1.52)];
+
+    let histogram = instrument.pitch_class_histogram(false, false, false);
+    assert_eq!(histogram, [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0]);
+    let weighted = instrument.pitch_class_histogram(true, true, true);
+    let total = 100.0 + 50.0 + 50.0;
+    compare_f64(weighted[0], 100.0 / total);
+    compare_f64(weighted[4], 50.0 / total);
+    compare_f64(weighted[7], 50.0 / total);
+
+    // The first note leads into both of the others
+    let transitions = instrument.pitch_class_transition_matrix(false, 0.05);
+    assert_eq!(transitions[0][4], 1.0);
+    assert_eq!(transitions[0][7], 1.0);
+    assert_eq!(transitions.iter().flatten().sum::<f64>(), 2.0);
+    assert_eq!(instrument.pitch_class_transition_matrix(true, 0.05)[0][4], 0.5);
+
+    instrument.is_drum = true;
+    assert_eq!(instrument.pitch_class_histogram(false, false, false), [0.0; 12]);
+    assert_eq!(instrument.pitch_class_transition_matrix(false, 0.05), [[0.0; 12]; 12]);
+}
+
+#[test]
+fn test_onsets_and_end_time() {
+    use super::{Instrument, PitchBend, RealTime};
+
+    let mut instrument = Instrument::<RealTime>::new(0, false);
+    assert_eq!(instrument.end_time(), 0.0);
+
+    instrument.notes = vec![note(60, 1.0, 2.0), note(60, 0.5, 1.0), note(60, 1.0, 1.5)];
+    assert_eq!(instrument.onsets(), [0.5, 1.0]);
+    assert_eq!(instrument.end_time(), 2.0);
+
+    instrument.pitch_bends = vec![PitchBend { bend: 8192, time: 2.5 }];
+    assert_eq!(instrument.end_time(), 2.5);
+    instrument.control_changes = vec![cc(64, 0, 3.0)];
+    assert_eq!(instrument.end_time(), 3.0);
+}
+
+#[test]
+fn test_stats() {
+    use super::{program_to_instrument_class, Instrument, RealTime, Stats};
+
+    let mut piano = Instrument::<RealTime>::new(0, false);
+    piano.notes = vec![
+        note_with_velocity(60, 40, 0.0, 2.0),
+        note_with_velocity(64, 80, 1.0, 2.0),
+        note_with_velocity(67, 120, 1.0, 3.0),
+    ];
+    let mut drums = Instrument::<RealTime>::new(0, true);
+    drums.notes = vec![note(36, 0.0, 0.1), note(38, 2.5, 2.6)];
+
+    let stats = Stats::new(&[piano, drums], 3.0, 1, 2.0);
+    assert_eq!(stats.note_count, 5);
+    assert_eq!(stats.max_polyphony, 3);
+    // One note for a second, three for a second and one for a second
+    compare_f64(stats.mean_polyphony, 5.0 / 3.0);
+    assert_eq!(stats.pitch_range, Some((60, 67)));
+    assert_eq!(stats.velocity_range, Some((40, 120)));
+    compare_f64(stats.velocity_mean, 88.0);
+    assert_eq!(stats.velocity_histogram, [0, 0, 1, 0, 0, 1, 2, 1]);
+    assert_eq!(stats.note_density, [2.0, 0.5]);
+    compare_f64(stats.drum_ratio, 0.4);
+    assert_eq!(stats.instruments[0].family, "Piano");
+    assert_eq!(stats.instruments[1].family, "Drums");
+
+    assert_eq!(program_to_instrument_class(33), "Bass");
+    assert_eq!(program_to_instrument_class(127), "Sound Effects");
+}
+
+#[test]
+fn test_key_estimation() {
+    use super::{estimate_key, estimate_key_curve, key_number_to_key_name, Instrument, KeyProfile, Note, RealTime};
+
+    let scale = |tonic: u8, start: f64| -> Vec<Note<RealTime>> {
+        // A major scale, lingering on the tonic triad
+        [0, 2, 4, 5, 7, 9, 11, 12, 7, 4, 0]
+            .iter()
+            .enumerate()
+            .map(|(i, step)| {
+                let end_time = start + (i + 1) as f64 * 0.25 + if step % 12 == 0 { 0.5 } else { 0.0 };
+                note(60 + tonic + step, start + i as f64 * 0.25, end_time)
+            })
+            .collect()
+    };
+
+    let mut instrument = Instrument::<RealTime>::new(0, false);
+    instrument.notes = scale(0, 0.0);
+    for profile in [KeyProfile::KrumhanslSchmuckler, KeyProfile::Temperley, KeyProfile::Albrecht] {
+        let durations = instrument.pitch_class_durations(0.0, f64::INFINITY);
+        assert_eq!(estimate_key(&durations, profile).unwrap().key_number, 0);
+    }
+
+    // A minor triad held over its scale
+    let mut minor = Instrument::<RealTime>::new(0, false);
+    minor.notes = [57, 60, 64, 59, 62, 65, 67]
+        .iter()
+        .map(|&pitch| note(pitch, 0.0, if pitch < 65 { 2.0 } else { 0.5 }))
+        .collect();
+    let durations = minor.pitch_class_durations(0.0, f64::INFINITY);
+    assert_eq!(estimate_key(&durations, KeyProfile::KrumhanslSchmuckler).unwrap().key_number, 21);
+    assert_eq!(estimate_key(&[0.0; 12], KeyProfile::KrumhanslSchmuckler), None);
+
+    // Modulating from C major to F# major
+    instrument.notes.extend(scale(6, 4.0));
+    let curve = estimate_key_curve(&[instrument], 4.0, 4.0, 8.0, KeyProfile::Temperley);
+    let keys: Vec<_> = curve.iter().map(|(start, key)| (*start, key.map(|key| key.key_number))).collect();
+    assert_eq!(keys, [(0.0, Some(0)), (4.0, Some(6))]);
+
+    assert_eq!(key_number_to_key_name(22), "Bb minor");
+    assert_eq!(key_number_to_key_name(6), "F# Major");
+}
+
+#[test]
+fn test_chord_estimation() {
+    use super::{estimate_chords, match_chord, ChordQuality, Instrument, MeterConverter, RealTime, TimeSignature};
+
+    let chord = |pitch_classes: &[usize], bass| {
+        let mut durations = [0.0; 12];
+        pitch_classes.iter().for_each(|pitch_class| durations[*pitch_class] = 1.0);
+        match_chord(&durations, bass).map(|chord| chord.symbol())
+    };
+    assert_eq!(chord(&[0, 4, 7], 0).as_deref(), Some("C"));
+    assert_eq!(chord(&[9, 0, 4], 9).as_deref(), Some("Am"));
+    assert_eq!(chord(&[7, 11, 2, 5], 7).as_deref(), Some("G7"));
+    assert_eq!(chord(&[11, 2, 5, 9], 11).as_deref(), Some("Bm7b5"));
+    assert_eq!(chord(&[0, 4, 7], 4).as_deref(), Some("C/E"));
+    assert_eq!(chord(&[], 0), None);
+
+    let mut piano = Instrument::<RealTime>::new(0, false);
+    piano.notes = vec![
+        note(48, 0.0, 1.0), note(64, 0.0, 1.0), note(67, 0.0, 1.0),
+        note(43, 1.0, 2.0), note(59, 1.0, 2.0), note(62, 1.0, 2.0), note(65, 1.0, 2.0),
+    ];
+    let mut drums = Instrument::<RealTime>::new(0, true);
+    drums.notes = vec![note(36, 0.0, 3.0)];
+
+    let segments = estimate_chords(&[piano, drums], &[0.0, 1.0, 2.0, 3.0]);
+    let chords: Vec<_> = segments.iter().map(|segment| segment.chord.map(|chord| chord.quality)).collect();
+    assert_eq!(chords, [Some(ChordQuality::Major), Some(ChordQuality::Dominant7), None]);
+    assert_eq!(segments[1].chord.unwrap().root, 7);
+
+    // Beats in 6/8 are eighth notes
+    let meter = MeterConverter::new(&[TimeSignature { numerator: 6, denominator: 8, time: 0 }], 100);
+    assert_eq!(meter.measure_ticks(700.0), [0.0, 300.0, 600.0]);
+    assert_eq!(meter.beat_ticks(700.0).len(), 14);
+}
+
+#[test]
+fn test_melody_extraction() {
+    use super::{extract_melody, Instrument, MelodyStrategy, RealTime};
+
+    let pitches = |instrument: &Instrument<RealTime>| -> Vec<u8> {
+        instrument.notes.iter().map(|note| note.pitch).collect()
+    };
+
+    let mut lead = Instrument::<RealTime>::new(73, false);
+    lead.name = "Lead".to_string();
+    lead.notes = vec![note(72, 0.0, 1.0), note(74, 1.0, 2.0), note(76, 2.0, 3.0), note(72, 3.0, 4.0)];
+    let mut piano = Instrument::<RealTime>::new(0, false);
+    piano.name = "Piano".to_string();
+    piano.notes = vec![
+        note(48, 0.0, 4.0), note(52, 0.0, 4.0), note(55, 0.0, 4.0),
+        // An inner note rising above the melody
+        note(79, 1.5, 2.5),
+    ];
+    let mut drums = Instrument::<RealTime>::new(0, true);
+    drums.notes = vec![note(81, 0.0, 4.0)];
+    let instruments = [piano, lead, drums];
+
+    let skyline = extract_melody(&instruments, MelodyStrategy::Skyline);
+    assert_eq!(pitches(&skyline), [72, 74, 79, 72]);
+    assert_eq!(skyline.notes[1].end_time, 1.5);
+    assert_eq!(skyline.program, 73);
+
+    let track = extract_melody(&instruments, MelodyStrategy::Track);
+    assert_eq!(pitches(&track), [72, 74, 76, 72]);
+    assert_eq!(track.name, "Lead");
+
+    let voice = extract_melody(&instruments, MelodyStrategy::Voice);
+    assert_eq!(pitches(&voice), [72, 74, 76, 72]);
+}
+
+#[test]
+fn test_voice_separation() {
+    use super::{Instrument, RealTime};
+
+    let mut piano = Instrument::<RealTime>::new(0, false);
+    piano.name = "Piano".to_string();
+    piano.notes = vec![
+        // Two voices moving by step, the lower one holding over a rest in the upper one
+        note(67, 0.0, 1.0), note(60, 0.0, 2.0),
+        note(69, 1.0, 2.0),
+        note(71, 2.5, 3.0), note(59, 2.0, 3.0),
+        // A third note joining in, held over by the voice below
+        note(72, 3.0, 4.0), note(64, 3.0, 4.0), note(48, 3.0, 4.0),
+    ];
+
+    let pitches = |voices: &[Instrument<RealTime>]| -> Vec<Vec<u8>> {
+        voices.iter().map(|voice| voice.notes.iter().map(|note| note.pitch).collect()).collect()
+    };
+
+    let voices = piano.separate_voices(usize::MAX);
+    assert_eq!(pitches(&voices), [vec![67, 69, 71, 72], vec![60, 59, 64], vec![48]]);
+    assert!(voices.iter().all(|voice| voice.name == "Piano" && voice.program == 0));
+
+    // Without room for the third voice, its note is left out
+    let voices = piano.separate_voices(2);
+    assert_eq!(pitches(&voices), [vec![67, 69, 71, 72], vec![60, 59, 64]]);
+}
+
+#[test]
+fn test_piano_roll_to_instrument() {
+    use super::piano_roll_to_instrument;
+
+    let mut roll = vec![vec![0.0; 6]; 128];
+    // A held note changing its velocity, and two notes of the same pitch
+    roll[60] = vec![0.0, 100.0, 100.0, 90.0, 0.0, 0.0];
+    roll[64] = vec![80.4, 0.0, 0.3, 0.6, 0.0, 200.0];
+
+    let notes = |instrument: super::Instrument<super::RealTime>| -> Vec<(u8, u8, f64, f64)> {
+        instrument.notes.iter().map(|note| (note.pitch, note.velocity, note.start_time, note.end_time)).collect()
+    };
+
+    let instrument = piano_roll_to_instrument(&roll, 10.0, 41, None);
+    assert_eq!(instrument.program, 41);
+    assert_eq!(
+        notes(instrument),
+        [(64, 80, 0.0, 0.1), (60, 100, 0.1, 0.4), (64, 1, 0.2, 0.4), (64, 127, 0.5, 0.6)]
+    );
+
+    // Onsets re-articulate held notes, but do not start notes on silent frames
+    let mut onsets = vec![vec![0.0; 6]; 128];
+    onsets[60] = vec![0.0, 1.0, 0.0, 1.0, 1.0, 0.0];
+    let instrument = piano_roll_to_instrument(&roll, 10.0, 0, Some(&onsets));
+    assert_eq!(
+        notes(instrument),
+        [(64, 80, 0.0, 0.1), (60, 100, 0.1, 0.3), (64, 1, 0.2, 0.4), (60, 90, 0.3, 0.4), (64, 127, 0.5, 0.6)]
+    );
+}
+
+#[test]
+fn test_transcription_evaluation() {
+    use super::{
+        active_frames, frame_scores, match_note_offsets, match_note_onsets, match_notes, match_notes_with_velocity,
+        note_scores, Note, NoteTolerances, RealTime,
+    };
+
+    let reference: Vec<Note<RealTime>> = vec![
+        note(60, 0.0, 1.0),
+        note_with_velocity(60, 40, 0.06, 0.5),
+        note_with_velocity(64, 80, 2.0, 3.0),
+        note_with_velocity(67, 60, 4.0, 4.5),
+    ];
+    let mut estimate: Vec<Note<RealTime>> = vec![
+        // Close to both notes of pitch 60, but only the second by its offset
+        note_with_velocity(60, 40, 0.03, 0.5),
+        note(60, 0.0, 1.05),
+        note_with_velocity(65, 80, 2.0, 3.0),
+        // Exactly at the onset and offset tolerances
+        note_with_velocity(67, 60, 4.05, 4.4),
+    ];
+    let tolerances = NoteTolerances::default();
+
+    let matching = match_notes(&reference, &estimate, &tolerances);
+    assert_eq!(matching, [(0, 1), (1, 0), (3, 3)]);
+    let scores = note_scores(&reference, &estimate, &matching, 1.0);
+    compare_f64(scores.precision, 0.75);
+    compare_f64(scores.recall, 0.75);
+    compare_f64(scores.f_measure, 0.75);
+    compare_f64(scores.average_overlap_ratio, (1.0 / 1.05 + 0.44 / 0.47 + 0.7) / 3.0);
+
+    // The greedy start matches the first estimated note with the first reference note,
+    // which the search has to undo to match all three
+    let without_offsets = NoteTolerances { offset_ratio: None, ..tolerances };
+    assert_eq!(match_notes(&reference, &estimate, &without_offsets), [(0, 1), (1, 0), (3, 3)]);
+    assert_eq!(match_note_onsets(&reference, &estimate, &tolerances).len(), 4);
+    assert_eq!(match_note_offsets(&reference, &estimate, &tolerances).len(), 4);
+    let strict = NoteTolerances { strict: true, ..tolerances };
+    assert_eq!(match_notes(&reference, &estimate, &strict), [(0, 1), (1, 0)]);
+
+    // Distances are rounded to four decimals, so float errors away from zero do not push them past the tolerance
+    for onset in [10.05, 10.05004] {
+        let late = [note(60, onset, onset + 1.0)];
+        assert_eq!(match_notes(&[note(60, 10.0, 11.0)], &late, &tolerances), [(0, 0)]);
+        assert_eq!(match_note_onsets(&[note(60, 10.0, 11.0)], &late, &tolerances), [(0, 0)]);
+    }
+    assert!(match_notes(&[note(60, 10.0, 11.0)], &[note(60, 10.0501, 11.0)], &without_offsets).is_empty());
+
+    // The estimated velocities are a linear function of the reference ones
+    assert_eq!(match_notes_with_velocity(&reference, &estimate, &tolerances, 0.1).len(), 3);
+    // and constant velocities only predict the mean
+    estimate.iter_mut().for_each(|note| note.velocity = 64);
+    assert!(match_notes_with_velocity(&reference, &estimate, &tolerances, 0.1).is_empty());
+
+    assert_eq!(note_scores(&reference, &[], &[], 1.0).f_measure, 0.0);
+
+    let scores = frame_scores(&active_frames(&reference, 10.0), &active_frames(&estimate, 10.0), 1.0);
+    compare_f64(scores.precision, 14.0 / 24.0);
+    compare_f64(scores.recall, 14.0 / 25.0);
+    compare_f64(scores.accuracy, 0.4);
+}
+
+#[test]
+fn test_score_alignment() {
+    use super::{align, Note, RealTime, TickConverter};
+
+    // A scale in quarter notes at 120 beats per minute
+    let score: Vec<Note<RealTime>> = [60, 62, 64, 65, 67, 69, 71, 72]
+        .into_iter()
+        .enumerate()
+        .map(|(i, pitch)| note(pitch, i as f64 * 0.5, (i + 1) as f64 * 0.5))
+        .collect();
+    // played at half the tempo for its first half, with a wrong note
+    let performance: Vec<Note<RealTime>> = vec![
+        note(60, 0.0, 1.0),
+        note(62, 1.0, 2.0),
+        note(64, 2.0, 3.0),
+        note(65, 3.0, 4.0),
+        note(67, 4.0, 4.5),
+        note(70, 4.5, 5.0),
+        note(71, 5.0, 5.5),
+        note(72, 5.5, 6.0),
+    ];
+    let converter = TickConverter::new(&[(0, 500_000)], 220);
+
+    let alignment = align(&score, &performance, &converter, 0.1, 0.3);
+    assert_eq!(alignment.notes, [(0, 0), (1, 1), (2, 2), (3, 3), (4, 4), (6, 6), (7, 7)]);
+    // Within a note the frames are alike, so the path may take any way through them
+    assert!((2.0..=3.0).contains(&alignment.warp.apply(1.25)));
+
+    let expected = [(0.0, 60.0), (1.0, 60.0), (2.0, 60.0), (3.0, 60.0), (4.0, 120.0), (5.0, 120.0)];
+    assert_eq!(alignment.tempo_curve.len(), expected.len());
+    for ((time, tempo), (expected_time, expected_tempo)) in alignment.tempo_curve.iter().zip(expected) {
+        compare_f64(*time, expected_time);
+        compare_f64(*tempo, expected_tempo);
+    }
+}
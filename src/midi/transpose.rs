@@ -0,0 +1,125 @@
+use super::{Instrument, KeySignature, Pitch, RealTime};
+
+const MAX_PITCH: i32 = 127;
+const MAJOR_SCALE: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+const MINOR_SCALE: [i32; 7] = [0, 2, 3, 5, 7, 8, 10];
+
+/// How to handle notes transposed outside of the MIDI pitch range.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PitchOverflow {
+    /// Removes the notes.
+    #[default]
+    Drop,
+    /// Moves the notes to the nearest pitch within the range.
+    Clamp,
+    /// Moves the notes by whole octaves until they lie within the range.
+    Fold,
+}
+
+impl PitchOverflow {
+    fn resolve(self, pitch: i32) -> Option<Pitch> {
+        let pitch = match self {
+            PitchOverflow::Drop => pitch,
+            PitchOverflow::Clamp => pitch.clamp(0, MAX_PITCH),
+            PitchOverflow::Fold if pitch < 0 => pitch.rem_euclid(12),
+            PitchOverflow::Fold if pitch > MAX_PITCH => MAX_PITCH - 11 + (pitch - MAX_PITCH + 11).rem_euclid(12),
+            PitchOverflow::Fold => pitch,
+        };
+        (0..=MAX_PITCH).contains(&pitch).then_some(pitch as Pitch)
+    }
+}
+
+/// Converts a key name like `"C major"`, `"Bb minor"` or `"f#"` into a key number like pretty-midi.
+/// Without a mode, upper case keys are major and lower case keys are minor.
+pub fn key_name_to_key_number(name: &str) -> Option<u8> {
+    let name = name.trim();
+    let mut chars = name.chars();
+    let letter = chars.next()?;
+    let tonic: i32 = match letter.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+
+    let rest = chars.as_str();
+    let mode = rest.trim_start_matches(['#', 'b']);
+    let accidentals = &rest[..rest.len() - mode.len()];
+    let shift = accidentals.chars().map(|c| if c == '#' { 1 } else { -1 }).sum::<i32>();
+
+    let minor = match mode.trim() {
+        "" => letter.is_ascii_lowercase(),
+        "M" => false,
+        "m" => true,
+        mode => match mode.to_ascii_lowercase().as_str() {
+            "major" | "maj" => false,
+            "minor" | "min" => true,
+            _ => return None,
+        },
+    };
+
+    let tonic = (tonic + shift).rem_euclid(12) as u8;
+    Some(if minor { tonic + 12 } else { tonic })
+}
+
+/// Moves a pitch by a number of steps along the scale of the key.
+/// Pitches outside of the scale keep their distance to the scale degree below them.
+fn diatonic_step(pitch: i32, steps: i32, key_number: u8) -> i32 {
+    let tonic = (key_number % 12) as i32;
+    let scale = if key_number < 12 { MAJOR_SCALE } else { MINOR_SCALE };
+
+    let octave = (pitch - tonic).div_euclid(12);
+    let offset = (pitch - tonic).rem_euclid(12);
+    let degree = scale.iter().rposition(|step| *step <= offset).unwrap_or(0);
+    let alteration = offset - scale[degree];
+
+    let degree = degree as i32 + steps;
+    tonic + 12 * (octave + degree.div_euclid(7)) + scale[degree.rem_euclid(7) as usize] + alteration
+}
+
+impl Instrument<RealTime> {
+    /// Moves every note by a number of semitones. Drum instruments are left as they are,
+    /// as their pitches select the sound rather than the pitch.
+    pub fn transpose(&mut self, semitones: i32, overflow: PitchOverflow) {
+        self.transpose_by(overflow, |pitch, _| pitch + semitones);
+    }
+
+    /// Moves every note by a number of steps along the scale of the key in effect when it starts.
+    /// The notes before the first key signature use that key, C major is used without any.
+    /// Drum instruments are left as they are.
+    pub fn transpose_diatonic(&mut self, steps: i32, keys: &[KeySignature<RealTime>], overflow: PitchOverflow) {
+        self.transpose_by(overflow, |pitch, time| {
+            let i = keys.partition_point(|key| key.time <= time).max(1) - 1;
+            let key_number = keys.get(i).map(|key| key.key_number).unwrap_or(0);
+            diatonic_step(pitch, steps, key_number)
+        });
+    }
+
+    fn transpose_by(&mut self, overflow: PitchOverflow, f: impl Fn(i32, f64) -> i32) {
+        if self.is_drum {
+            return;
+        }
+        self.notes.retain_mut(|note| match overflow.resolve(f(note.pitch as i32, note.start_time)) {
+            Some(pitch) => {
+                note.pitch = pitch;
+                true
+            }
+            None => false,
+        });
+    }
+}
+
+impl KeySignature<RealTime> {
+    /// The key signature moved by a number of semitones, keeping its mode.
+    pub fn transpose(&self, semitones: i32) -> Self {
+        let tonic = (self.key_number as i32 % 12 + semitones).rem_euclid(12) as u8;
+        KeySignature {
+            key_number: self.key_number / 12 * 12 + tonic,
+            time: self.time,
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use super::{Instrument, Note, RealTime};
+use std::cmp::Reverse;
+
+/// The cost, in semitones per second, of resting in a voice before continuing it.
+const REST_COST: f64 = 4.0;
+/// The cost of cutting short the sounding note of a voice to continue it.
+const CUT_COST: f64 = 24.0;
+/// The cost of every other voice that a voice would cross by continuing with a note.
+const CROSSING_COST: f64 = 12.0;
+/// Notes may overlap by this many seconds, as in legato playing, without counting as cut short.
+const LEGATO_OVERLAP: f64 = 0.05;
+
+struct Voice {
+    notes: Vec<Note<RealTime>>,
+}
+
+impl Voice {
+    fn last(&self) -> &Note<RealTime> {
+        self.notes.last().unwrap()
+    }
+
+    fn mean_pitch(&self) -> f64 {
+        self.notes.iter().map(|note| note.pitch as f64).sum::<f64>() / self.notes.len() as f64
+    }
+}
+
+/// Splits notes into at most `max_voices` monophonic voices, continuing each voice
+/// with the notes closest in pitch and time while avoiding voices crossing each other.
+/// A new voice only starts when all voices are sounding, so there are no more voices than notes sounding at once.
+/// The notes of a voice do not overlap, notes continuing a voice cut the previous note short.
+/// Notes starting together with `max_voices` others or more are left out.
+/// The voices are ordered from the highest to the lowest.
+pub fn separate_voices(notes: &[Note<RealTime>], max_voices: usize) -> Vec<Vec<Note<RealTime>>> {
+    let mut notes = notes.to_vec();
+    notes.sort_by(|a, b| a.start_time.total_cmp(&b.start_time).then(b.pitch.cmp(&a.pitch)));
+
+    let mut voices: Vec<Voice> = vec![];
+    for note in notes {
+        let pitch = note.pitch as f64;
+        // Voices are kept in order of their last pitch, from the highest
+        let higher = voices.partition_point(|voice| voice.last().pitch > note.pitch);
+        let not_lower = voices.partition_point(|voice| voice.last().pitch >= note.pitch);
+        // The cost of continuing a voice, and whether its sounding note gets cut short
+        let cost = |(i, voice): (usize, &Voice)| -> Option<(f64, bool)> {
+            let last = voice.last();
+            if last.start_time >= note.start_time {
+                return None;
+            }
+
+            let mut cost = (pitch - last.pitch as f64).abs();
+            let rest = note.start_time - last.end_time;
+            let cut = -rest > LEGATO_OVERLAP;
+            if rest > 0.0 {
+                cost += REST_COST * rest;
+            } else if cut {
+                cost += CUT_COST;
+            }
+
+            // The higher voices below this one and the lower voices above it
+            let crossed = higher.saturating_sub(i + 1) + i.saturating_sub(not_lower);
+            Some((cost + CROSSING_COST * crossed as f64, cut))
+        };
+
+        let costs: Vec<_> = voices.iter().enumerate().filter_map(|voice| Some((voice.0, cost(voice)?))).collect();
+        let cheapest = |cut_allowed: bool| {
+            costs
+                .iter()
+                .filter(|(_, (_, cut))| cut_allowed || !cut)
+                .min_by(|(_, (a, _)), (_, (b, _))| a.total_cmp(b))
+                .map(|(i, _)| *i)
+        };
+        // A new voice only starts when all voices are sounding
+        let best = match cheapest(false) {
+            None if voices.len() < max_voices => None,
+            None => cheapest(true),
+            free => free,
+        };
+
+        match best {
+            Some(i) => {
+                let last = voices[i].notes.last_mut().unwrap();
+                last.end_time = last.end_time.min(note.start_time);
+                voices[i].notes.push(note);
+            }
+            None if voices.len() < max_voices => voices.push(Voice { notes: vec![note] }),
+            None => {}
+        }
+        voices.sort_by_key(|voice| Reverse(voice.last().pitch));
+    }
+
+    voices.sort_by(|a, b| b.mean_pitch().total_cmp(&a.mean_pitch()));
+    voices.into_iter().map(|voice| voice.notes).collect()
+}
+
+impl Instrument<RealTime> {
+    /// Splits the notes into at most `max_voices` monophonic voices, see [`separate_voices`].
+    /// Every voice keeps the program, name, pitch bends and control changes of the instrument.
+    pub fn separate_voices(&self, max_voices: usize) -> Vec<Instrument<RealTime>> {
+        separate_voices(&self.notes, max_voices)
+            .into_iter()
+            .map(|notes| Instrument {
+                name: self.name.clone(),
+                notes,
+                pitch_bends: self.pitch_bends.clone(),
+                control_changes: self.control_changes.clone(),
+                ..Instrument::<RealTime>::new(self.program, self.is_drum)
+            })
+            .collect()
+    }
+}
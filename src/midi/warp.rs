@@ -0,0 +1,175 @@
+use super::{Instrument, Metadata, MidiTime, RealTime, TempoChange, TickConverter};
+
+/// The largest tempo, in microseconds per beat, that fits into a MIDI tempo event.
+const MAX_TEMPO: f64 = 0xFF_FFFF as f64;
+
+/// A piecewise-linear mapping from one timeline onto another, like `numpy.interp`.
+/// Times before the first or after the last original time are clamped to the ends.
+#[derive(Debug, Clone)]
+pub struct TimeWarp {
+    original: Vec<f64>,
+    adjusted: Vec<f64>,
+}
+
+impl TimeWarp {
+    pub fn new(original: Vec<f64>, adjusted: Vec<f64>) -> Self {
+        assert!(
+            original.len() == adjusted.len() && original.len() >= 2,
+            "A time warp needs at least two pairs of times."
+        );
+        assert!(
+            original.windows(2).all(|w| w[0] < w[1]),
+            "The original times have to be strictly increasing."
+        );
+        assert!(
+            adjusted.windows(2).all(|w| w[0] <= w[1]),
+            "The adjusted times can not decrease."
+        );
+
+        TimeWarp { original, adjusted }
+    }
+
+    pub fn apply(&self, time: f64) -> f64 {
+        let i = self.original.partition_point(|t| *t <= time);
+        if i == 0 {
+            return self.adjusted[0];
+        }
+        if i == self.original.len() {
+            return self.adjusted[i - 1];
+        }
+
+        let (t0, t1) = (self.original[i - 1], self.original[i]);
+        let (a0, a1) = (self.adjusted[i - 1], self.adjusted[i]);
+        a0 + (time - t0) / (t1 - t0) * (a1 - a0)
+    }
+
+    fn original_range(&self) -> (f64, f64) {
+        (self.original[0], self.original[self.original.len() - 1])
+    }
+
+    fn adjusted_range(&self) -> (f64, f64) {
+        (self.adjusted[0], self.adjusted[self.adjusted.len() - 1])
+    }
+}
+
+/// Moves the events onto the adjusted timeline like pretty-midi.
+/// Only the events within the adjusted times are kept,
+/// along with the last event at its start, which sets the initial state.
+pub fn adjust_event_times<E>(events: &mut Vec<E>, time: impl Fn(&mut E) -> &mut f64, warp: &TimeWarp) {
+    let (start, end) = warp.adjusted_range();
+
+    let mut timed: Vec<(f64, E)> = events.drain(..).map(|mut event| (*time(&mut event), event)).collect();
+    timed.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+    let initial = timed.iter().rposition(|(t, _)| warp.apply(*t) == start);
+    for (i, (t, mut event)) in timed.into_iter().enumerate() {
+        let t = warp.apply(t);
+        if Some(i) == initial || (start < t && t < end) {
+            *time(&mut event) = t;
+            events.push(event);
+        }
+    }
+}
+
+impl Instrument<RealTime> {
+    /// Moves all events onto the adjusted timeline, dropping the notes
+    /// that do not lie entirely within the original times.
+    pub fn adjust_times(&mut self, warp: &TimeWarp) {
+        let (start, end) = warp.original_range();
+
+        self.notes.retain(|note| note.start_time >= start && note.end_time <= end);
+        for note in &mut self.notes {
+            note.start_time = warp.apply(note.start_time);
+            note.end_time = warp.apply(note.end_time);
+        }
+        self.remove_invalid_notes();
+
+        adjust_event_times(&mut self.pitch_bends, |bend| &mut bend.time, warp);
+        adjust_event_times(&mut self.control_changes, |cc| &mut cc.time, warp);
+    }
+}
+
+impl Metadata<RealTime> {
+    pub fn adjust_times(&mut self, warp: &TimeWarp) {
+        adjust_event_times(&mut self.key_signature_changes, |ks| &mut ks.time, warp);
+        adjust_event_times(&mut self.time_signature_changes, |ts| &mut ts.time, warp);
+        adjust_event_times(&mut self.lyrics, |lyric| &mut lyric.time, warp);
+        adjust_event_times(&mut self.text_events, |text| &mut text.time, warp);
+    }
+}
+
+impl TickConverter {
+    /// Rewrites the tempo map so that converting ticks gives the adjusted times.
+    ///
+    /// The ticks between the original times keep their musical position,
+    /// shifted so that the first original time lands at the first adjusted time,
+    /// while the tempo changes within each stretch of the warp.
+    /// The ticks before the first adjusted time are played at the tempo found there
+    /// and the tempo changes after the last original time are kept as they are.
+    pub fn adjust_times(&self, warp: &TimeWarp) -> TickConverter {
+        let resolution = self.resolution() as f64;
+        let (start, end) = warp.original_range();
+        let original_tick = |time| self.time_to_beat(time) * resolution;
+        let (start_tick, end_tick) = (original_tick(start), original_tick(end));
+        let tempo_at = |tick: f64| {
+            let i = self.tempo_map.partition_point(|(t, _)| *t as f64 <= tick).max(1) - 1;
+            self.tempo_map[i].1
+        };
+
+        // Fill the time before the first adjusted time with the tempo found there
+        let lead_in = warp.adjusted[0];
+        let lead_in_ticks =
+            (lead_in * 1e6 * resolution / tempo_at(start_tick) as f64).round() as MidiTime;
+        let shift = |tick: f64| (tick - start_tick + lead_in_ticks as f64).round().max(0.0) as MidiTime;
+
+        let mut breakpoints: Vec<(MidiTime, f64)> = warp
+            .original
+            .iter()
+            .zip(&warp.adjusted)
+            .map(|(original, adjusted)| (shift(original_tick(*original)), *adjusted))
+            .chain(
+                self.tempo_map
+                    .iter()
+                    .map(|(tick, _)| *tick as f64)
+                    .filter(|tick| start_tick < *tick && *tick < end_tick)
+                    .map(|tick| (shift(tick), warp.apply(self.beat_to_time(tick / resolution)))),
+            )
+            .collect();
+        breakpoints.sort_by_key(|(tick, _)| *tick);
+        breakpoints.dedup_by_key(|(tick, _)| *tick);
+
+        let tempo = |seconds: f64, ticks: MidiTime| {
+            (seconds * 1e6 * resolution / ticks as f64).round().clamp(1.0, MAX_TEMPO) as u32
+        };
+
+        let mut tempo_map: Vec<TempoChange> = Vec::new();
+        if lead_in_ticks > 0 {
+            tempo_map.push((0, tempo(lead_in, lead_in_ticks)));
+        }
+        for w in breakpoints.windows(2) {
+            let ((tick, time), (next_tick, next_time)) = (w[0], w[1]);
+            tempo_map.push((tick, tempo(next_time - time, next_tick - tick)));
+        }
+
+        let (last_tick, _) = breakpoints[breakpoints.len() - 1];
+        tempo_map.push((last_tick, tempo_at(end_tick)));
+        tempo_map.extend(
+            self.tempo_map
+                .iter()
+                .filter(|(tick, _)| *tick as f64 > end_tick)
+                .map(|(tick, tempo)| (shift(*tick as f64), *tempo)),
+        );
+
+        // Keep a single change per tick and drop the ones not changing the tempo
+        let mut deduplicated: Vec<TempoChange> = Vec::with_capacity(tempo_map.len());
+        for change in tempo_map {
+            match deduplicated.last_mut() {
+                Some(last) if last.0 == change.0 => *last = change,
+                _ => deduplicated.push(change),
+            }
+        }
+        deduplicated.dedup_by_key(|(_, tempo)| *tempo);
+
+        TickConverter::new(&deduplicated, self.resolution())
+    }
+}
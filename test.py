@@ -1,3 +1,4 @@
+import pretty_midi_rs
 from pretty_midi_rs import MidiObject
 import pretty_midi
 
@@ -22,3 +23,544 @@ for (note_a, note_b) in zip(a_data.instruments[0].notes, b_data.instruments[0].n
         print(f"end {note_a.end} == {note_b.end} -> {note_a.start - note_b.start}")
         break
     
+
+# The compat module against pretty-midi itself
+import io
+
+import numpy as np
+from pretty_midi_rs import compat
+
+
+def build_reference() -> bytes:
+    """Writes a file with pretty-midi using the features the compat module reads."""
+    midi = pretty_midi.PrettyMIDI(initial_tempo=100.0)
+    piano = pretty_midi.Instrument(program=0, name="Piano")
+    for n, pitch in enumerate([60, 64, 67, 72, 71, 67, 64, 60]):
+        piano.notes.append(pretty_midi.Note(velocity=60 + n * 5, pitch=pitch, start=n * 0.3, end=n * 0.3 + 0.5))
+    piano.pitch_bends.append(pretty_midi.PitchBend(pitch=4096, time=1.0))
+    piano.pitch_bends.append(pretty_midi.PitchBend(pitch=0, time=1.5))
+    piano.pitch_bends.append(pretty_midi.PitchBend(pitch=2048, time=1.8))
+    piano.pitch_bends.append(pretty_midi.PitchBend(pitch=-1024, time=2.2))
+    piano.control_changes.append(pretty_midi.ControlChange(number=64, value=100, time=0.5))
+    piano.control_changes.append(pretty_midi.ControlChange(number=64, value=0, time=1.2))
+    drums = pretty_midi.Instrument(program=0, is_drum=True, name="Drums")
+    for n in range(8):
+        drums.notes.append(pretty_midi.Note(velocity=100, pitch=36 + n % 2 * 2, start=n * 0.3, end=n * 0.3 + 0.1))
+    midi.instruments = [piano, drums]
+    midi.time_signature_changes = [pretty_midi.TimeSignature(4, 4, 0.0), pretty_midi.TimeSignature(6, 8, 1.2)]
+    midi.key_signature_changes = [pretty_midi.KeySignature(2, 0.0), pretty_midi.KeySignature(14, 1.2)]
+    midi.lyrics = [pretty_midi.Lyric("la", 0.3), pretty_midi.Lyric("lü", 0.6)]
+    midi.text_events = [pretty_midi.Text("verse", 0.0)]
+    buffer = io.BytesIO()
+    midi.write(buffer)
+    return buffer.getvalue()
+
+
+def assert_events_equal(name, a, b, fields):
+    assert len(a) == len(b), f"{name}: {len(a)} != {len(b)}"
+    for event_a, event_b in zip(a, b):
+        for field in fields:
+            value_a, value_b = getattr(event_a, field), getattr(event_b, field)
+            if isinstance(value_b, float):
+                assert comp_float(value_a, value_b), f"{name}.{field}: {value_a} != {value_b}"
+            else:
+                assert value_a == value_b, f"{name}.{field}: {value_a} != {value_b}"
+
+
+def assert_arrays_close(name, a, b):
+    a, b = np.asarray(a), np.asarray(b)
+    assert a.shape == b.shape, f"{name}: shape {a.shape} != {b.shape}"
+    assert np.allclose(a, b, atol=0.001), f"{name}: values differ"
+
+
+def compare_compat(data: bytes, charset="latin1"):
+    ours = compat.PrettyMIDI(io.BytesIO(data), charset=charset)
+    theirs = pretty_midi.PrettyMIDI(io.BytesIO(data), charset=charset)
+
+    assert ours.resolution == theirs.resolution
+    assert_events_equal("key_signature_changes", ours.key_signature_changes, theirs.key_signature_changes, ["key_number", "time"])
+    assert_events_equal("time_signature_changes", ours.time_signature_changes, theirs.time_signature_changes, ["numerator", "denominator", "time"])
+    assert_events_equal("lyrics", ours.lyrics, theirs.lyrics, ["text", "time"])
+    assert_events_equal("text_events", ours.text_events, theirs.text_events, ["text", "time"])
+    assert_events_equal("instruments", ours.instruments, theirs.instruments, ["program", "is_drum", "name"])
+
+    for ours_i, theirs_i in zip(ours.instruments, theirs.instruments):
+        name = ours_i.name
+        # pretty-midi orders notes by their end, the compat module by their start
+        ours_notes = sorted(ours_i.notes, key=lambda note: (note.start, note.pitch))
+        theirs_notes = sorted(theirs_i.notes, key=lambda note: (note.start, note.pitch))
+        assert_events_equal(f"{name}.notes", ours_notes, theirs_notes, ["pitch", "velocity", "start", "end"])
+        assert_events_equal(f"{name}.pitch_bends", ours_i.pitch_bends, theirs_i.pitch_bends, ["pitch", "time"])
+        assert_events_equal(f"{name}.control_changes", ours_i.control_changes, theirs_i.control_changes, ["number", "value", "time"])
+        assert_arrays_close(f"{name}.get_onsets", ours_i.get_onsets(), theirs_i.get_onsets())
+        assert comp_float(ours_i.get_end_time(), theirs_i.get_end_time())
+        assert_arrays_close(f"{name}.get_piano_roll", ours_i.get_piano_roll(), theirs_i.get_piano_roll())
+        times = np.arange(0, 2.0, 0.05)
+        assert_arrays_close(f"{name}.get_piano_roll(times)", ours_i.get_piano_roll(times=times), theirs_i.get_piano_roll(times=times))
+        assert_arrays_close(f"{name}.get_chroma", ours_i.get_chroma(fs=50), theirs_i.get_chroma(fs=50))
+        for use_duration in [False, True]:
+            for use_velocity in [False, True]:
+                assert_arrays_close(
+                    f"{name}.get_pitch_class_histogram",
+                    ours_i.get_pitch_class_histogram(use_duration, use_velocity, normalize=True),
+                    theirs_i.get_pitch_class_histogram(use_duration, use_velocity, normalize=True),
+                )
+        assert_arrays_close(
+            f"{name}.get_pitch_class_transition_matrix",
+            ours_i.get_pitch_class_transition_matrix(time_thresh=0.25),
+            theirs_i.get_pitch_class_transition_matrix(time_thresh=0.25),
+        )
+
+    for ours_a, theirs_a in zip(ours.get_tempo_changes(), theirs.get_tempo_changes()):
+        assert_arrays_close("get_tempo_changes", ours_a, theirs_a)
+    for tick in [0, 1, 100, 1000, 10000]:
+        assert comp_float(ours.tick_to_time(tick), theirs.tick_to_time(tick))
+    for time in [0.0, 0.1, 1.0, 2.5]:
+        assert ours.time_to_tick(time) == theirs.time_to_tick(time)
+    assert comp_float(ours.get_end_time(), theirs.get_end_time())
+    assert_arrays_close("get_onsets", ours.get_onsets(), theirs.get_onsets())
+    assert_arrays_close("get_piano_roll", ours.get_piano_roll(), theirs.get_piano_roll())
+    assert_arrays_close("get_chroma", ours.get_chroma(), theirs.get_chroma())
+    assert_arrays_close("get_beats", ours.get_beats(), theirs.get_beats())
+    assert_arrays_close("get_beats(start_time)", ours.get_beats(0.2), theirs.get_beats(0.2))
+    assert_arrays_close("get_downbeats", ours.get_downbeats(), theirs.get_downbeats())
+    for ours_a, theirs_a in zip(ours.estimate_tempi(), theirs.estimate_tempi()):
+        assert_arrays_close("estimate_tempi", ours_a, theirs_a)
+    assert comp_float(ours.estimate_tempo(), theirs.estimate_tempo())
+    assert_arrays_close(
+        "get_pitch_class_histogram",
+        ours.get_pitch_class_histogram(True, True, True),
+        theirs.get_pitch_class_histogram(True, True, True),
+    )
+    assert_arrays_close(
+        "get_pitch_class_transition_matrix",
+        ours.get_pitch_class_transition_matrix(True),
+        theirs.get_pitch_class_transition_matrix(True),
+    )
+
+    ours.remove_invalid_notes()
+    theirs.remove_invalid_notes()
+    original, adjusted = [0.0, 1.0, ours.get_end_time()], [0.0, 2.0, ours.get_end_time() + 1.0]
+    ours.adjust_times(original, adjusted)
+    theirs.adjust_times(original, adjusted)
+    assert_arrays_close("adjust_times", ours.get_onsets(), theirs.get_onsets())
+    assert_events_equal("adjust_times.lyrics", ours.lyrics, theirs.lyrics, ["text", "time"])
+    for ours_i, theirs_i in zip(ours.instruments, theirs.instruments):
+        assert_events_equal(f"adjust_times.{ours_i.name}.control_changes", ours_i.control_changes, theirs_i.control_changes, ["number", "value", "time"])
+
+
+with open("./test_data/source.mid", "rb") as source:
+    compare_compat(source.read())
+reference = build_reference()
+compare_compat(reference)
+
+empty_ours, empty_theirs = compat.PrettyMIDI(resolution=480, initial_tempo=90.0), pretty_midi.PrettyMIDI(resolution=480, initial_tempo=90.0)
+assert empty_ours.resolution == empty_theirs.resolution
+assert comp_float(empty_ours.tick_to_time(960), empty_theirs.tick_to_time(960))
+assert empty_ours.get_piano_roll().shape == empty_theirs.get_piano_roll().shape
+fractional_ours, fractional_theirs = compat.PrettyMIDI(initial_tempo=90.5), pretty_midi.PrettyMIDI(initial_tempo=90.5)
+assert comp_float(fractional_ours.tick_to_time(10000), fractional_theirs.tick_to_time(10000))
+assert fractional_ours.time_to_tick(30.0) == fractional_theirs.time_to_tick(30.0)
+
+# Instruments read from a file give back its ticks, even when the tempo makes the times in seconds inexact
+import mido
+
+inexact = pretty_midi.PrettyMIDI(resolution=7, initial_tempo=180.0002)
+piano = pretty_midi.Instrument(program=0)
+for n in range(200):
+    piano.notes.append(pretty_midi.Note(velocity=100, pitch=40 + n % 40, start=n * 0.123, end=n * 0.123 + 0.071))
+inexact.instruments.append(piano)
+buffer = io.BytesIO()
+inexact.write(buffer)
+
+expected = []
+for track in mido.MidiFile(file=io.BytesIO(buffer.getvalue())).tracks:
+    tick = 0
+    for message in track:
+        tick += message.time
+        if message.type == "note_on" and message.velocity > 0:
+            expected.append((message.note, tick))
+
+ticks = MidiObject.from_bytes(buffer.getvalue()).to_ticks()
+assert sorted((note.pitch, note.start) for i in ticks.instruments for note in i.notes) == sorted(expected)
+
+
+# The Python layer of the crate on small files written byte by byte
+import struct
+
+
+def variable_length(value: int) -> bytes:
+    data = [value & 0x7F]
+    value >>= 7
+    while value:
+        data.append(value & 0x7F | 0x80)
+        value >>= 7
+    return bytes(reversed(data))
+
+
+def build_file(tracks, division=480) -> bytes:
+    """Writes a format 1 file from tracks of `(delta, message)` pairs, at 120 bpm unless a track sets a tempo."""
+    data = b"MThd" + struct.pack(">IHHH", 6, 1, len(tracks), division)
+    for events in tracks:
+        track = b"".join(variable_length(delta) + message for delta, message in events) + b"\x00\xff\x2f\x00"
+        data += b"MTrk" + struct.pack(">I", len(track)) + track
+    return data
+
+
+def note_on(pitch, velocity=100, channel=0):
+    return bytes([0x90 | channel, pitch, velocity])
+
+
+def note_off(pitch, channel=0):
+    return bytes([0x80 | channel, pitch, 0])
+
+
+def control_change(number, value, channel=0):
+    return bytes([0xB0 | channel, number, value])
+
+
+def meta_event(kind, data):
+    return bytes([0xFF, kind]) + variable_length(len(data)) + data
+
+
+def assert_raises_value_error(name, call):
+    try:
+        call()
+    except ValueError:
+        return
+    raise AssertionError(f"{name} did not raise a ValueError")
+
+
+# Sustain pedal, with 480 ticks per beat at 120 bpm a beat lasts half a second
+pedal_file = build_file([[
+    (0, control_change(64, 127)),
+    (0, note_on(60)),
+    (480, note_off(60)),
+    (480, control_change(64, 0)),
+]])
+assert MidiObject.from_bytes(pedal_file).instruments[0].notes[0].end == 0.5
+assert MidiObject.from_bytes(pedal_file, sustain_threshold=64).instruments[0].notes[0].end == 1.0
+pedal = MidiObject.from_bytes(pedal_file)
+pedal.apply_sustain_pedal()
+assert pedal.instruments[0].notes[0].end == 1.0
+assert [(i.start, i.end) for i in pedal.instruments[0].get_pedal_intervals()] == [(0.0, 1.0)]
+assert pedal.instruments[0].get_pedal_intervals(number=66) == []
+
+# Invalid notes and clean, the zero length note only survives pairings other than pretty-midi's
+overlap_file = build_file([[
+    (0, note_on(60)),
+    (0, note_off(60)),
+    (0, note_on(62)),
+    (480, note_off(62)),
+    (0, note_on(64)),
+    (240, note_on(64)),
+    (480, note_off(64)),
+    (240, note_off(64)),
+]])
+overlapping = MidiObject.from_bytes(overlap_file, note_pairing="fifo")
+overlapping.remove_invalid_notes()
+assert [(n.pitch, n.start, n.end) for n in overlapping.instruments[0].notes] == [(62, 0.0, 0.5), (64, 0.5, 1.25), (64, 0.75, 1.5)]
+overlapping = MidiObject.from_bytes(overlap_file, note_pairing="fifo")
+overlapping.clean()
+assert [(n.pitch, n.start, n.end) for n in overlapping.instruments[0].notes] == [(62, 0.0, 0.5), (64, 0.5, 0.75), (64, 0.75, 1.5)]
+overlapping.instruments[0].clean(end_time=1.0)
+assert [(n.pitch, n.start, n.end) for n in overlapping.instruments[0].notes] == [(62, 0.0, 0.5), (64, 0.5, 0.75), (64, 0.75, 1.0)]
+
+# Adjusting times warps the notes, dangling notes, metadata and tempo map alike
+warp_file = build_file([[
+    (0, note_on(60)),
+    (480, note_off(60)),
+    (0, note_on(62)),
+    (480, note_off(62)),
+    (0, note_on(64)),
+    (960, meta_event(0x05, b"la")),
+]])
+warped = MidiObject.from_bytes(warp_file)
+assert [(note.pitch, note.start) for note in warped.dangling_notes] == [(64, 1.0)]
+warped.adjust_times([0.0, 1.0, 3.0], [0.0, 2.0, 4.0])
+assert [(n.pitch, n.start, n.end) for n in warped.instruments[0].notes] == [(60, 0.0, 1.0), (62, 1.0, 2.0)]
+assert [note.start for note in warped.dangling_notes] == [2.0]
+assert [(lyric.text, lyric.time) for lyric in warped.lyrics] == [("la", 3.0)]
+assert warped.get_tempo_changes() == ([0.0, 2.0], [60.0, 120.0])
+assert warped.time_to_tick(2.0) == 960
+assert_raises_value_error("adjust_times with different lengths", lambda: warped.adjust_times([0.0, 1.0], [0.0]))
+assert_raises_value_error("adjust_times with a single time", lambda: warped.adjust_times([0.0], [0.0]))
+assert_raises_value_error("adjust_times with decreasing times", lambda: warped.adjust_times([1.0, 0.0], [0.0, 1.0]))
+assert_raises_value_error("adjust_times with negative times", lambda: warped.adjust_times([0.0, 1.0], [-1.0, 1.0]))
+
+# Slicing carries the tempo, signatures and controllers in effect at the start
+slice_file = build_file([[
+    (0, meta_event(0x58, bytes([3, 2, 24, 8]))),
+    (0, meta_event(0x59, bytes([2, 0]))),
+    (0, control_change(7, 90)),
+    (0, note_on(60)),
+    (960, note_off(60)),
+    (0, note_on(62)),
+    (480, meta_event(0x51, (250000).to_bytes(3, "big"))),
+    (0, meta_event(0x05, b"la")),
+    (480, note_off(62)),
+]])
+whole = MidiObject.from_bytes(slice_file)
+sliced = whole.slice(0.5, 1.5)
+assert [(n.pitch, n.start, n.end) for n in sliced.instruments[0].notes] == [(60, 0.0, 0.5), (62, 0.5, 1.0)]
+assert sliced.get_tempo_changes() == ([0.0, 1.0], [120.0, 240.0])
+assert [(ts.numerator, ts.denominator, ts.time) for ts in sliced.time_signature_changes] == [(3, 4, 0.0)]
+assert [(ks.key_number, ks.time) for ks in sliced.key_signature_changes] == [(2, 0.0)]
+assert [(cc.number, cc.value, cc.time) for cc in sliced.instruments[0].control_changes] == [(7, 90, 0.0)]
+assert sliced.lyrics == []
+assert sliced.time_to_bbt(1.25) == (2, 1, 0.0)
+assert len(whole.slice(0.5, 1.5, clip_notes=False).instruments[0].notes) == 0
+assert [(n.start, n.end) for n in whole.instruments[0].slice(0.5, 1.5).notes] == [(0.0, 0.5), (0.5, 1.0)]
+assert_raises_value_error("slice with an empty range", lambda: whole.slice(1.0, 1.0))
+assert_raises_value_error("slice with a negative start", lambda: whole.instruments[0].slice(-1.0, 1.0))
+
+# Merging plays both files at once, concatenating starts the second one on the first tick after the gap
+first = MidiObject.from_bytes(build_file([[(0, meta_event(0x03, b"Piano")), (0, note_on(60)), (480, note_off(60))]]))
+second = MidiObject.from_bytes(build_file([
+    [(0, meta_event(0x51, (250000).to_bytes(3, "big"))), (0, meta_event(0x03, b"Piano")), (0, note_on(64)), (480, note_off(64))],
+    [(0, note_on(36, channel=9)), (240, note_off(36, channel=9))],
+]))
+merged = first.merge(second)
+assert [(i.name, i.is_drum, [(n.pitch, n.start, n.end) for n in i.notes]) for i in merged.instruments] == [
+    ("Piano", False, [(60, 0.0, 0.5), (64, 0.0, 0.25)]),
+    ("", True, [(36, 0.0, 0.125)]),
+]
+assert merged.get_tempo_changes() == ([0.0], [120.0])
+concatenated = first.concat(second, gap=0.25)
+assert [(i.name, [(n.pitch, n.start, n.end) for n in i.notes]) for i in concatenated.instruments] == [
+    ("Piano", [(60, 0.0, 0.5), (64, 0.75, 1.0)]),
+    ("", [(36, 0.75, 0.875)]),
+]
+assert concatenated.get_tempo_changes() == ([0.0, 0.75], [120.0, 240.0])
+assert concatenated.get_end_time() == 1.0
+assert first.concat(second).get_tempo_changes() == ([0.0, 0.5], [120.0, 240.0])
+assert_raises_value_error("concat with a negative gap", lambda: first.concat(second, gap=-1.0))
+
+# Transposition leaves the drums and moves the key signatures, diatonic steps follow the key in effect
+transpose_file = build_file([[
+    (0, meta_event(0x59, bytes([0xFE, 0]))),
+    (0, note_on(62)),
+    (0, note_on(126)),
+    (0, note_on(36, channel=9)),
+    (480, note_off(62)),
+    (0, note_off(126)),
+    (0, note_off(36, channel=9)),
+]])
+for out_of_range, pitches in [("drop", [65]), ("clamp", [65, 127]), ("fold", [65, 117])]:
+    transposed = MidiObject.from_bytes(transpose_file)
+    transposed.transpose(3, out_of_range)
+    assert [[n.pitch for n in i.notes] for i in transposed.instruments] == [pitches, [36]], out_of_range
+    assert [ks.key_number for ks in transposed.key_signature_changes] == [1]
+transposed = MidiObject.from_bytes(transpose_file)
+transposed.transpose_diatonic(1)
+assert [n.pitch for n in transposed.instruments[0].notes] == [63]
+transposed = MidiObject.from_bytes(transpose_file)
+transposed.transpose_diatonic(1, key="C major")
+assert [n.pitch for n in transposed.instruments[0].notes] == [64]
+transposed.instruments[0].transpose_diatonic(-1, key=0)
+assert [n.pitch for n in transposed.instruments[0].notes] == [62]
+assert_raises_value_error("transpose with an unknown policy", lambda: transposed.transpose(1, "wrap"))
+assert_raises_value_error("transpose_diatonic with an unknown key", lambda: transposed.transpose_diatonic(1, key="H major"))
+assert_raises_value_error("transpose_diatonic with a key number above 23", lambda: transposed.transpose_diatonic(1, key=24))
+
+# Quantization to eighth notes, which last a quarter of a second at 120 bpm
+quantize_file = build_file([[(10, note_on(60)), (215, note_off(60)), (0, note_on(62)), (300, note_off(62))]])
+
+
+def quantized(grid, **options):
+    midi = MidiObject.from_bytes(quantize_file)
+    midi.quantize(grid, **options)
+    return [(note.start, note.end) for note in midi.instruments[0].notes]
+
+
+assert quantized("1/8") == [(0.0, 0.25), (0.25, 0.5)]
+assert quantized(1 / 8) == quantized("1/8")
+assert quantized("1/8", swing=0.5) == [(0.0, 0.375), (0.375, 0.5)]
+assert quantized("1/8", quantize_ends=False)[1] == (0.25, 0.5625)
+half_way = quantized("1/8", strength=0.5)
+assert comp_float(half_way[0][0], 0.0052) and comp_float(half_way[1][1], 0.5234)
+assert [(round(start, 3), round(end, 3)) for start, end in quantized("1/8t")] == [(0.0, 0.167), (0.167, 0.5)]
+assert_raises_value_error("quantize with an unknown grid", lambda: quantized("1/7x"))
+assert_raises_value_error("quantize with a zero grid", lambda: quantized(0.0))
+assert_raises_value_error("quantize with a strength above 1", lambda: quantized("1/8", strength=1.5))
+assert_raises_value_error("quantize with a swing of 1", lambda: quantized("1/8", swing=1.0))
+
+# Pitch class features leave out the drums, the module functions take notes as tuples
+features_file = build_file([[
+    (0, note_on(60, 50)),
+    (480, note_off(60)),
+    (0, note_on(64)),
+    (480, note_off(64)),
+    (0, note_on(67)),
+    (960, note_off(67)),
+    (0, note_on(36, channel=9)),
+    (10, note_off(36, channel=9)),
+]])
+features = MidiObject.from_bytes(features_file)
+assert features.get_pitch_class_histogram() == [1.0, 0, 0, 0, 1.0, 0, 0, 1.0, 0, 0, 0, 0]
+weighted = features.get_pitch_class_histogram(use_duration=True, use_velocity=True, normalize=True)
+assert all(comp_float(a, b) for a, b in zip(weighted, [1 / 7, 0, 0, 0, 2 / 7, 0, 0, 4 / 7, 0, 0, 0, 0]))
+assert features.instruments[1].get_pitch_class_histogram() == [0.0] * 12
+transitions = features.get_pitch_class_transition_matrix(normalize=True)
+assert transitions[0][4] == transitions[4][7] == 0.5 and sum(map(sum, transitions)) == 1.0
+assert pretty_midi_rs.pitch_class_histogram([(60, 100, 0.0, 1.0), (64, 50, 0.0, 2.0)], use_duration=True) == [1.0, 0, 0, 0, 2.0, 0, 0, 0, 0, 0, 0, 0]
+assert pretty_midi_rs.pitch_class_histogram([(60, 100, 0.0, 1.0)], is_drum=True) == [0.0] * 12
+assert pretty_midi_rs.pitch_class_transition_matrix([(60, 100, 0.0, 1.0), (64, 50, 1.0, 2.0)])[0][4] == 1.0
+assert pretty_midi_rs.pitch_class_transition_matrix([(60, 100, 0.0, 1.0), (64, 50, 1.5, 2.0)])[0][4] == 0.0
+
+# Onsets are distinct and sorted, the end time takes in control changes and text events
+onsets_file = build_file([
+    [(0, note_on(60)), (0, note_on(64)), (480, note_off(60)), (0, note_off(64)), (240, note_on(67)), (240, note_off(67)), (480, control_change(1, 3))],
+    [(0, note_on(36, channel=9)), (240, note_off(36, channel=9)), (2000, meta_event(0x01, b"end"))],
+])
+onsets = MidiObject.from_bytes(onsets_file)
+assert onsets.get_onsets() == [0.0, 0.75]
+assert [i.get_onsets() for i in onsets.instruments] == [[0.0, 0.75], [0.0]]
+assert [i.get_end_time() for i in onsets.instruments] == [1.5, 0.25]
+assert comp_float(onsets.get_end_time(), 2240 / 960)
+
+# Statistics, with the drums counted in the velocities but not in the polyphony and pitch range
+stats_file = build_file([
+    [
+        (0, meta_event(0x03, b"Lead")),
+        (0, bytes([0xC0, 40])),
+        (0, note_on(60, 20)),
+        (0, note_on(64, 40)),
+        (480, note_off(60)),
+        (0, note_off(64)),
+        (480, note_on(72, 120)),
+        (480, note_off(72)),
+    ],
+    [(0, note_on(36, 100, channel=9)), (240, note_off(36, channel=9))],
+])
+stats = MidiObject.from_bytes(stats_file).stats()
+assert (stats.duration, stats.note_count, stats.max_polyphony) == (1.5, 4, 2)
+assert stats.pitch_range == (60, 72) and stats.velocity_range == (20, 120)
+assert stats.velocity_mean == 70.0 and stats.velocity_histogram == [0, 1, 1, 0, 0, 0, 1, 1]
+assert stats.note_density == [3.0, 1.0] and stats.tempo_changes == 1 and stats.drum_ratio == 0.25
+assert [(i.program, i.is_drum, i.name, i.family, i.note_count) for i in stats.instruments] == [
+    (40, False, "Lead", "Strings", 3),
+    (0, True, "", "Drums", 1),
+]
+assert MidiObject.from_bytes(stats_file).stats(density_window=0.5).note_density == [6.0, 0.0, 2.0]
+assert_raises_value_error("stats with an empty density window", lambda: MidiObject.from_bytes(stats_file).stats(density_window=0.0))
+
+# Key estimation, a C major scale followed by an F# major one
+c_major = [event for pitch in [60, 62, 64, 65, 67, 69, 71, 72, 67, 64, 60] for event in [(0, note_on(pitch)), (480, note_off(pitch))]]
+f_sharp_major = [event for pitch in [66, 68, 70, 71, 73, 75, 77, 78] for event in [(0, note_on(pitch)), (480, note_off(pitch))]]
+scale = MidiObject.from_bytes(build_file([c_major]))
+for profile in ["krumhansl", "temperley", "albrecht"]:
+    key = scale.estimate_key(profile)
+    assert (key.key_number, key.key_name) == (0, "C Major"), profile
+    assert 0.9 < key.correlation <= 1.0
+curve = MidiObject.from_bytes(build_file([c_major + f_sharp_major])).estimate_key_curve(window=4.0)
+assert [(start, key.key_name) for start, key in curve[:4]] == [(0.0, "C Major"), (2.0, "C Major"), (4.0, "F# Major"), (6.0, "F# Major")]
+assert MidiObject.from_bytes(build_file([[(0, note_on(36, channel=9)), (240, note_off(36, channel=9))]])).estimate_key() is None
+assert_raises_value_error("estimate_key with an unknown profile", lambda: scale.estimate_key("bach"))
+assert_raises_value_error("estimate_key_curve with a negative hop", lambda: scale.estimate_key_curve(hop=-1.0))
+
+# Chords are labeled per beat or bar of the time signature, with beats in its note value
+def chord_events(pitches, duration):
+    return [(0, note_on(pitch)) for pitch in pitches] + [(0 if i else duration, note_off(pitch)) for i, pitch in enumerate(pitches)]
+
+
+chords = MidiObject.from_bytes(build_file([
+    [(0, meta_event(0x58, bytes([3, 2, 24, 8])))] + chord_events([60, 64, 67], 1440) + chord_events([57, 60, 64], 960) + chord_events([55, 59, 62, 65], 480),
+]))
+assert [(c.start, c.end, c.symbol) for c in chords.estimate_chords()] == [
+    (0.0, 0.5, "C"), (0.5, 1.0, "C"), (1.0, 1.5, "C"), (1.5, 2.0, "Am"), (2.0, 2.5, "Am"), (2.5, 3.0, "G7"),
+]
+assert [(c.root, c.quality, c.bass) for c in chords.estimate_chords()[-1:]] == [(7, "7", 7)]
+assert [(c.start, c.end) for c in chords.estimate_chords("bar")] == [(0.0, 1.5), (1.5, 3.0)]
+eighths = MidiObject.from_bytes(build_file([[(0, meta_event(0x58, bytes([6, 3, 24, 8])))] + chord_events([60, 64, 67], 1440)]))
+assert [c.end for c in eighths.estimate_chords()] == [0.25, 0.5, 0.75, 1.0, 1.25, 1.5]
+assert [(c.start, c.end, c.symbol) for c in eighths.estimate_chords("bar")] == [(0.0, 1.5, "C")]
+drums_only = MidiObject.from_bytes(build_file([[(0, note_on(36, channel=9)), (480, note_off(36, channel=9))]]))
+assert [(c.symbol, c.root, c.quality, c.bass) for c in drums_only.estimate_chords()] == [("N", None, None, None)]
+assert_raises_value_error("estimate_chords with an unknown resolution", lambda: chords.estimate_chords("measure"))
+
+# Melody extraction from a pad with a high note and a lead track named like a melody
+pad_track = [(0, meta_event(0x03, b"Pad"))] + chord_events([48, 79, 55], 960) + chord_events([50, 53, 57], 960)
+lead_track = [(0, meta_event(0x03, b"Melody"))] + [event for pitch in [72, 74, 76, 74] for event in [(0, note_on(pitch)), (480, note_off(pitch))]]
+melody_file = build_file([pad_track, lead_track])
+skyline = MidiObject.from_bytes(melody_file).extract_melody()
+assert [(n.pitch, n.start, n.end) for n in skyline.notes] == [(79, 0.0, 1.0), (76, 1.0, 1.5), (74, 1.5, 2.0)]
+track_melody = MidiObject.from_bytes(melody_file).extract_melody("track")
+assert [n.pitch for n in track_melody.notes] == [72, 74, 76, 74]
+assert_raises_value_error("extract_melody with an unknown strategy", lambda: MidiObject.from_bytes(melody_file).extract_melody("loudest"))
+
+# Voice separation, from the highest voice to the lowest
+voices = MidiObject.from_bytes(melody_file).instruments[0].separate_voices()
+assert [[n.pitch for n in voice.notes] for voice in voices] == [[79, 50], [55, 57], [48, 53]]
+assert all(voice.name == "Pad" for voice in voices)
+assert len(MidiObject.from_bytes(melody_file).instruments[0].separate_voices(max_voices=2)) == 2
+assert [n.pitch for n in MidiObject.from_bytes(melody_file).extract_melody("voice").notes] == [72, 74, 76, 74]
+assert_raises_value_error("separate_voices without voices", lambda: voices[0].separate_voices(max_voices=0))
+
+# Piano rolls back to instruments, from lists and from numpy arrays read through the buffer protocol
+roll = [[0.0] * 10 for _ in range(128)]
+roll[60][2:6] = [80.0] * 4
+roll[64][4:10] = [100.0] * 6
+from_roll = pretty_midi_rs.piano_roll_to_instrument(roll, fs=10, program=5)
+assert from_roll.program == 5
+assert [(n.pitch, n.velocity, n.start, n.end) for n in from_roll.notes] == [(60, 80, 0.2, 0.6), (64, 100, 0.4, 1.0)]
+from_array = pretty_midi_rs.piano_roll_to_instrument(np.array(roll), fs=10, program=5)
+assert [(n.pitch, n.start, n.end) for n in from_array.notes] == [(n.pitch, n.start, n.end) for n in from_roll.notes]
+roll_onsets = [[0.0] * 10 for _ in range(128)]
+roll_onsets[60][2] = roll_onsets[60][4] = roll_onsets[64][4] = 1.0
+split = pretty_midi_rs.piano_roll_to_instrument(roll, fs=10, onsets=roll_onsets)
+assert [(n.pitch, n.start, n.end) for n in split.notes] == [(60, 0.2, 0.4), (60, 0.4, 0.6), (64, 0.4, 1.0)]
+assert_raises_value_error("piano_roll_to_instrument with too few rows", lambda: pretty_midi_rs.piano_roll_to_instrument(roll[:10]))
+assert_raises_value_error("piano_roll_to_instrument with ragged rows", lambda: pretty_midi_rs.piano_roll_to_instrument(roll[:127] + [[0.0]]))
+assert_raises_value_error("piano_roll_to_instrument without frames per second", lambda: pretty_midi_rs.piano_roll_to_instrument(roll, fs=0))
+assert_raises_value_error("piano_roll_to_instrument with mismatched onsets", lambda: pretty_midi_rs.piano_roll_to_instrument(roll, onsets=roll_onsets[:5]))
+
+# Transcription evaluation, the estimate misses one pitch by a semitone
+reference_notes = MidiObject.from_bytes(build_file([[e for p in [60, 62, 64, 65] for e in [(0, note_on(p)), (480, note_off(p))]]]))
+estimated_notes = MidiObject.from_bytes(build_file([[e for p in [60, 62, 63, 65] for e in [(0, note_on(p, 50)), (480, note_off(p))]]]))
+scores = pretty_midi_rs.evaluate_transcription(reference_notes, estimated_notes)
+assert list(scores)[:4] == ["Precision", "Recall", "F-measure", "Average_Overlap_Ratio"]
+assert (scores["F-measure"], scores["F-measure_no_offset"], scores["Onset_F-measure"], scores["Offset_F-measure"]) == (0.75, 0.75, 1.0, 1.0)
+assert pretty_midi_rs.evaluate_transcription(reference_notes.instruments[0], estimated_notes.instruments[0], pitch_tolerance=150.0)["F-measure"] == 1.0
+assert pretty_midi_rs.evaluate_transcription_velocity(reference_notes, estimated_notes)["F-measure"] == 0.75
+frames = pretty_midi_rs.evaluate_frames(reference_notes, estimated_notes, fs=10)
+assert (frames["Precision"], frames["Recall"], frames["Accuracy"]) == (0.75, 0.75, 0.6)
+assert pretty_midi_rs.evaluate_frames(roll, roll)["F-measure"] == 1.0
+assert_raises_value_error("evaluate_transcription with a negative tolerance", lambda: pretty_midi_rs.evaluate_transcription(reference_notes, estimated_notes, onset_tolerance=-1.0))
+assert_raises_value_error("evaluate_frames without frames per second", lambda: pretty_midi_rs.evaluate_frames(reference_notes, estimated_notes, fs=0.0))
+
+# Alignment of a performance played at 80 bpm to a score at 120 bpm
+scale_events = [e for p in [60, 62, 64, 65, 67, 69] for e in [(0, note_on(p)), (480, note_off(p))]]
+aligned_score = MidiObject.from_bytes(build_file([scale_events]))
+performance = MidiObject.from_bytes(build_file([[(0, meta_event(0x51, (750000).to_bytes(3, "big")))] + scale_events]))
+alignment = performance.align_to_score(aligned_score)
+assert [(n.pitch, n.score_start, n.performance_start) for n in alignment.notes] == [
+    (60, 0.0, 0.0), (62, 0.5, 0.75), (64, 1.0, 1.5), (65, 1.5, 2.25), (67, 2.0, 3.0), (69, 2.5, 3.75),
+]
+assert [(n.score_note, n.performance_note) for n in alignment.notes] == [(i, i) for i in range(6)]
+assert all(tempo == 80.0 for _, tempo in alignment.tempo_curve)
+assert abs(alignment.score_to_performance_time(1.0) - 1.5) < 0.1
+assert_raises_value_error("align_to_score without a hop", lambda: performance.align_to_score(aligned_score, hop=0.0))
+assert_raises_value_error("align_to_score with a negative tolerance", lambda: performance.align_to_score(aligned_score, tolerance=-0.1))
+
+# Bar, beat and tick positions across a change from 4/4 to 6/8, where beats are eighth notes
+meter = MidiObject.from_bytes(build_file([[
+    (0, meta_event(0x58, bytes([4, 2, 24, 8]))),
+    (0, note_on(60)),
+    (1920, meta_event(0x58, bytes([6, 3, 24, 8]))),
+    (2880, note_off(60)),
+]]))
+assert [meter.time_to_bbt(time) for time in [0.0, 2.0, 2.25, 3.5]] == [(1, 1, 0.0), (2, 1, 0.0), (2, 2, 0.0), (3, 1, 0.0)]
+bar, beat, tick = meter.time_to_bbt(0.6)
+assert (bar, beat) == (1, 2) and comp_float(tick, 96.0)
+assert [meter.get_measure_index(time) for time in [0.0, 1.99, 2.0, 3.5]] == [0, 0, 1, 2]
+assert (meter.bbt_to_time(1, 2, 240.0), meter.bbt_to_time(2, 1, 0.0), meter.bbt_to_time(2, 6, 0.0)) == (0.75, 2.0, 3.25)
+assert_raises_value_error("bbt_to_time in bar 0", lambda: meter.bbt_to_time(0, 1, 0.0))
+assert_raises_value_error("bbt_to_time on beat 0", lambda: meter.bbt_to_time(1, 0, 0.0))
+assert_raises_value_error("bbt_to_time past the last beat of the bar", lambda: meter.bbt_to_time(1, 5, 0.0))
+beatless = MidiObject.from_bytes(build_file([[(0, meta_event(0x58, bytes([0, 2, 24, 8]))), (0, note_on(60)), (960, note_off(60))]]))
+assert beatless.time_signature_changes == [] and len(beatless.estimate_chords("bar")) == 1
+
+# Note pairing is chosen when loading, unknown pairings are rejected
+assert [(n.start, n.end) for n in MidiObject.from_bytes(overlap_file, note_pairing="lifo").instruments[0].notes][2:] == [(0.5, 1.5), (0.75, 1.25)]
+assert_raises_value_error("from_bytes with an unknown note pairing", lambda: MidiObject.from_bytes(overlap_file, note_pairing="random"))